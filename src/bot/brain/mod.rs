@@ -1,24 +0,0 @@
-// Copyright (C) 2016 Matti Hänninen
-//
-// This file is part of Umpteenth Anion.
-//
-// Umpteenth Anion is free software: you can redistribute it and/or modify it
-// under the terms of the GNU General Public License as published by the Free
-// Software Foundation, either version 3 of the License, or (at your option)
-// any later version.
-//
-// Umpteenth Anion is distributed in the hope that it will be useful, but
-// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
-// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
-// for more details.
-//
-// You should have received a copy of the GNU General Public License along
-// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
-
-pub mod brain;
-pub mod lone_expander;
-pub mod probe;
-pub mod simple;
-pub mod teddy;
-
-pub use brain::brain::{Brain, Mold, run_forever};
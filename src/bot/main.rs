@@ -21,55 +21,102 @@ extern crate getopts;
 extern crate rand;
 extern crate ua;
 
-use std::collections::BTreeMap;
 use std::env;
-use std::io::Write;
-use std::str::FromStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
 
-mod brain;
-mod params;
+use ua::brain::{self, Mold};
+use ua::params::{self, ParamFile, Params, Schema};
 
-enum Brain
+/// The mold of a brain whose name has already been checked.
+fn mold_of(name: &str) -> Box<Mold>
 {
-    LoneExpander,
-    Probe,
-    Simple,
-    Teddy,
+    brain::mold_of(name).unwrap()
 }
 
-impl Brain
+/// The parameters understood by any of the brains.
+fn schema_of<S>(names: &[S]) -> Schema
+    where S: AsRef<str>
 {
-    fn default() -> Self
-    {
-        if cfg!(feature = "probe") {
-            Brain::Probe
-        } else {
-            Brain::LoneExpander
+    let mut schema = Schema::new();
+    for name in names.iter() {
+        schema.merge(&mold_of(name.as_ref()).schema());
+    }
+    schema
+}
+
+/// Works out the parameters of each brain of the chain.  The values given on
+/// the command line override those in the parameter file.
+fn resolve_params(brains: &[String],
+                  file: &ParamFile,
+                  overrides: &Params)
+    -> Result<Vec<Params>, String>
+{
+    // A parameter file may carry values for brains not in the chain but it
+    // is an error to have values no brain understands.
+    let known = schema_of(&brain::names());
+    let chain = schema_of(brains);
+    for k in file.global.keys() {
+        if known.spec(k).is_none() {
+            return Err(params::Error::Unknown(k.clone()).to_string());
+        }
+    }
+    for k in overrides.keys() {
+        if chain.spec(k).is_none() {
+            return Err(params::Error::Unknown(k.clone()).to_string());
         }
     }
+    for (name, section) in file.sections.iter() {
+        try!(try!(brain::mold_of(name))
+                 .schema()
+                 .check(section)
+                 .map_err(|e| format!("[{}] {}", name, e)));
+    }
+    brains.iter()
+          .map(|name| {
+              let schema = mold_of(name).schema();
+              let params = file.params_for(name, &schema, overrides);
+              schema.check(&params).map(|_| params)
+          })
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(|e| e.to_string())
 }
 
-impl FromStr for Brain
+/// Lists the parameters of every brain for the usage information.
+fn describe_params() -> String
 {
-    type Err = String;
-    fn from_str(s: &str) -> Result<Self, Self::Err>
-    {
-        match s {
-            "lone_expander" => Ok(Brain::LoneExpander),
-            "probe" => Ok(Brain::Probe),
-            "simple" => Ok(Brain::Simple),
-            "teddy" => Ok(Brain::Teddy),
-            _ => Err(format!("no brain with name '{}'", s)),
+    let mut description = String::new();
+    for name in brain::names() {
+        let schema = mold_of(name).schema();
+        description.push_str(&format!("\nParameters of {}:", name));
+        if schema.is_empty() {
+            description.push_str(" none\n");
+            continue;
+        }
+        description.push('\n');
+        for spec in schema.specs() {
+            description.push_str(&format!("    {}={} ({} to {})\n        {}\n",
+                                          spec.name,
+                                          spec.default,
+                                          spec.min,
+                                          spec.max,
+                                          spec.description));
         }
     }
+    description
 }
 
-#[allow(dead_code)]
 struct Config
 {
-    brain: Brain,
+    brains: Vec<String>,
+    budget: Duration,
     log_path: Option<String>,
-    params: params::Params,
+    log_level: ua::log::Level,
+    params: Vec<Params>,
+    show_schema: bool,
+    trace_path: Option<String>,
+    playback_path: Option<String>,
 }
 
 enum OptionParsing
@@ -99,24 +146,92 @@ fn parse_options() -> Result<OptionParsing, String>
     let mut opts = Options::new();
     opts.parsing_style(ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "Display this usage information")
-        .optopt("b", "brain", "Select the bot brain to use", "NAME")
-        .optopt("l", "log", "Produce log of internal events to file", "FILE");
+        .optflag("S",
+                 "schema",
+                 "Print the parameters of the selected brains, one per line, \
+                  and exit")
+        .optopt("b",
+                "brain",
+                "Select the bot brain to use; a comma separated list of \
+                 brains makes the later ones stand in for the earlier ones \
+                 when they panic or run out of time",
+                "NAME[,NAME...]")
+        .optopt("B",
+                "budget",
+                &format!("Time given to the brain for each turn (default {})",
+                         brain::DEFAULT_TURN_BUDGET_MS),
+                "MILLISECONDS")
+        .optopt("l", "log", "Produce log of internal events to file", "FILE")
+        .optopt("p",
+                "params",
+                "Read brain parameters from file; KEY=VALUE arguments \
+                 override the values in it",
+                "FILE")
+        .optopt("v",
+                "verbosity",
+                "Level of detail in the log: error, warn, info (default), \
+                 debug or trace",
+                "LEVEL")
+        .optopt("t",
+                "trace",
+                "Record the traffic with the environment to file",
+                "FILE")
+        .optopt("r",
+                "replay-trace",
+                "Play back a recorded trace and report how the actions \
+                 differ from the recorded ones",
+                "FILE");
     let args = env::args().collect::<Vec<String>>();
     if let Ok(matches) = opts.parse(&args[1..]) {
         if matches.opt_present("h") {
-            let brief = format!("usage: {} [ options ]", args[0]);
-            Ok(OptionParsing::ShowUsage(opts.usage(&brief)))
+            let brief = format!("usage: {} [ options ] [ KEY=VALUE ... ]",
+                                args[0]);
+            Ok(OptionParsing::ShowUsage(opts.usage(&brief) +
+                                        &describe_params()))
         } else {
+            let brains = match matches.opt_str("b") {
+                Some(brain_names) => {
+                    let names = brain_names.split(',')
+                                           .map(|s| s.to_owned())
+                                           .collect::<Vec<_>>();
+                    for name in names.iter() {
+                        try!(brain::mold_of(name));
+                    }
+                    names
+                }
+                None => vec![brain::default_name().to_owned()],
+            };
+            let file = match matches.opt_str("p") {
+                Some(path) => {
+                    try!(ParamFile::load(&path)
+                             .map_err(|e| format!("{}: {}", path, e)))
+                }
+                None => ParamFile::new(),
+            };
+            let overrides = try!(matches.free
+                                        .iter()
+                                        .map(|f| parse_free(&f))
+                                        .collect::<Result<Params, _>>()
+                                        .map_err(|s| s.to_owned()));
+            let params = try!(resolve_params(&brains, &file, &overrides));
             let config = Config {
-                brain: match matches.opt_str("b") {
-                    Some(brain_name) => try!(Brain::from_str(&brain_name)),
-                    None => Brain::default(),
-                },
+                brains: brains,
+                budget: Duration::from_millis(match matches.opt_str("B") {
+                    Some(ms) => {
+                        try!(ms.parse::<u64>()
+                               .map_err(|_| "bad time budget".to_owned()))
+                    }
+                    None => brain::DEFAULT_TURN_BUDGET_MS,
+                }),
                 log_path: matches.opt_str("l"),
-                params: try!(matches.free.iter()
-                             .map(|f| parse_free(&f))
-                             .collect::<Result<BTreeMap<String, f32>, _>>()
-                             .map_err(|s| s.to_owned())),
+                log_level: match matches.opt_str("v") {
+                    Some(level) => try!(level.parse::<ua::log::Level>()),
+                    None => ua::log::Level::Info,
+                },
+                trace_path: matches.opt_str("t"),
+                playback_path: matches.opt_str("r"),
+                params: params,
+                show_schema: matches.opt_present("S"),
             };
             Ok(OptionParsing::Config(config))
         }
@@ -125,18 +240,87 @@ fn parse_options() -> Result<OptionParsing, String>
     }
 }
 
+fn play_back(molds: &[&Mold], config: &Config, path: &str) -> !
+{
+    let trace = match ua::trace::Trace::load(path) {
+        Ok(trace) => trace,
+        Err(why) => {
+            writeln!(std::io::stderr(), "Error while loading trace: {:?}", why)
+                .unwrap();
+            std::process::exit(1);
+        }
+    };
+    match brain::play_back(&trace, molds, &config.params) {
+        Ok(differences) => {
+            for difference in differences.iter() {
+                println!("{}", difference);
+            }
+            std::process::exit(if differences.is_empty() { 0 } else { 1 });
+        }
+        Err(why) => {
+            writeln!(std::io::stderr(),
+                     "Error while playing back trace: {:?}",
+                     why)
+                .unwrap();
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main()
 {
     match parse_options() {
         Ok(OptionParsing::Config(config)) => {
-            let mold: Box<brain::Mold> = match config.brain {
-                Brain::Teddy => Box::new(brain::teddy::TeddyMold),
-                Brain::LoneExpander => Box::new(brain::lone_expander::LoneMold),
-                Brain::Probe => Box::new(brain::probe::ProbeMold),
-                Brain::Simple => Box::new(brain::simple::SimpleMold),
+            if config.show_schema {
+                print!("{}", schema_of(&config.brains));
+                std::process::exit(0);
+            }
+            let molds = config.brains
+                              .iter()
+                              .map(|name| mold_of(name))
+                              .collect::<Vec<_>>();
+            let molds = molds.iter().map(|m| m.as_ref()).collect::<Vec<_>>();
+            if let Some(ref path) = config.playback_path {
+                play_back(&molds, &config, path);
+            }
+            let trace: Option<Box<Write>> = match config.trace_path {
+                Some(ref path) => {
+                    match File::create(path) {
+                        Ok(file) => Some(Box::new(BufWriter::new(file))),
+                        Err(why) => {
+                            writeln!(std::io::stderr(),
+                                     "Error while creating trace: {}",
+                                     why)
+                                .unwrap();
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                None => None,
+            };
+            let mut log = match config.log_path {
+                Some(ref path) => {
+                    match File::create(path) {
+                        Ok(file) => {
+                            ua::Log::to(Box::new(BufWriter::new(file)),
+                                        config.log_level)
+                        }
+                        Err(why) => {
+                            writeln!(std::io::stderr(),
+                                     "Error while creating log: {}",
+                                     why)
+                                .unwrap();
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                None => ua::Log::none(),
             };
-            if let Err(why) = brain::brain::run_forever(mold.as_ref(),
-                                                        &config.params) {
+            if let Err(why) = brain::run_forever(&molds,
+                                                 &config.params,
+                                                 config.budget,
+                                                 &mut log,
+                                                 trace) {
                 writeln!(std::io::stderr(),
                          "Error while driving brain: {:?}",
                          why)
@@ -0,0 +1,501 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use action::Action;
+use deadline::Deadline;
+use io;
+use log::{Level, Log};
+use params::{Params, Schema};
+use trace::{self, Difference, Trace};
+use world::{Environment, State};
+
+/// The time a brain is given to decide its moves, counting from the moment
+/// the frame is received.  The environment allows for one second per turn;
+/// the rest is margin for the transmission and parsing.
+pub const DEFAULT_TURN_BUDGET_MS: u64 = 800;
+
+pub trait Brain
+{
+    /// Decides the actions for the turn.  The brain should return its best
+    /// actions before the `deadline` passes.
+    fn tick(&mut self,
+            state: &State,
+            deadline: &Deadline,
+            log: &mut Log)
+        -> Vec<Action>;
+}
+
+pub trait Mold
+{
+    fn name(&self) -> Cow<str>;
+
+    /// The parameters the brain understands.
+    fn schema(&self) -> Schema
+    {
+        Schema::new()
+    }
+
+    fn reanimate(&self,
+                 params: &Params,
+                 environment: Environment,
+                 init_state: &State,
+                 log: &mut Log)
+        -> Box<Brain>;
+}
+
+#[derive(Debug)]
+pub enum Error
+{
+    Io(io::Error),
+    // None of the brains in the chain could be reanimated
+    NoBrain,
+}
+
+impl From<io::Error> for Error
+{
+    fn from(e: io::Error) -> Self
+    {
+        Error::Io(e)
+    }
+}
+
+/// The fraction (1/N) of the turn budget held back for the fallback brains.
+const FALLBACK_RESERVE_DIVISOR: u32 = 4;
+
+/// A brain together with the name of the mold it came from.
+struct Link
+{
+    name: String,
+    brain: Box<Brain>,
+}
+
+/// The message given to `panic!` or a stand-in if there is none.
+pub fn panic_message(payload: &Box<Any + Send>) -> String
+{
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown cause".to_owned()
+    }
+}
+
+/// Reports a failure of a brain both to the log and to the standard error.
+fn report_failure(log: &mut Log, args: ::std::fmt::Arguments)
+{
+    log.error(args);
+    log.flush();
+    writeln!(::std::io::stderr(), "{}", args).unwrap();
+}
+
+/// Reanimates the brains of the molds skipping those that panic.  Each mold
+/// is given the parameters at the same position in `params`.
+fn reanimate_chain(molds: &[&Mold],
+                   params: &[Params],
+                   environment: &Environment,
+                   init_state: &State,
+                   log: &mut Log)
+    -> Vec<Link>
+{
+    assert_eq!(molds.len(), params.len());
+    let mut chain = Vec::new();
+    for (mold, params) in molds.iter().zip(params.iter()) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            mold.reanimate(params, environment.clone(), init_state, log)
+        }));
+        match result {
+            Ok(brain) => {
+                chain.push(Link {
+                    name: mold.name().into_owned(),
+                    brain: brain,
+                })
+            }
+            Err(payload) => {
+                report_failure(log,
+                               format_args!("Brain {} panicked while \
+                                             reanimating: {}",
+                                            mold.name(),
+                                            panic_message(&payload)));
+            }
+        }
+    }
+    chain
+}
+
+/// Ticks the brains of the chain in order until one of them comes up with
+/// its actions.  Each brain but the last one gets a shortened deadline so
+/// that there is still time left for the brains after it should it panic.
+/// A brain missing its deadline still makes the moves since running the
+/// next brain then would only be later still, and once the turn budget has
+/// run out no further brain is ticked.
+fn tick_chain(chain: &mut [Link],
+              state: &State,
+              deadline: &Deadline,
+              log: &mut Log)
+    -> Vec<Action>
+{
+    let n = chain.len() as u32;
+    let reserve = deadline.budget() / FALLBACK_RESERVE_DIVISOR;
+    for (i, link) in chain.iter_mut().enumerate() {
+        let i = i as u32;
+        let is_last = i + 1 == n;
+        let link_deadline = if is_last {
+            deadline.clone()
+        } else {
+            deadline.shortened_by(reserve * (n - 1 - i) / (n - 1))
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            link.brain.tick(state, &link_deadline, log)
+        }));
+        match result {
+            Ok(actions) => {
+                if deadline.has_passed() {
+                    report_failure(log,
+                                   format_args!("Brain {} ran out of time \
+                                                 on turn {}",
+                                                link.name,
+                                                state.turn));
+                } else if link_deadline.has_passed() {
+                    log.warn(format_args!("Brain {} missed its deadline on \
+                                           turn {} but within the budget",
+                                          link.name,
+                                          state.turn));
+                }
+                return actions;
+            }
+            Err(payload) => {
+                report_failure(log,
+                               format_args!("Brain {} panicked on turn {}: \
+                                             {}",
+                                            link.name,
+                                            state.turn,
+                                            panic_message(&payload)));
+            }
+        }
+        if deadline.has_passed() {
+            break;
+        }
+    }
+    // Nobody came up with anything in time; just stay still.
+    vec![]
+}
+
+/// Drives the chain of brains through the standard input and output until
+/// the environment hangs up.  If `trace` is given the traffic is copied into
+/// it.
+pub fn run_forever(molds: &[&Mold],
+                   params: &[Params],
+                   budget: Duration,
+                   log: &mut Log,
+                   trace: Option<Box<Write>>)
+    -> Result<(), Error>
+{
+    let mut connection = io::Connection::new();
+    if let Some(trace) = trace {
+        connection.trace_to(trace);
+    }
+    run(&mut connection, molds, params, budget, log)
+}
+
+/// Feeds the input recorded in `trace` to fresh brains and compares their
+/// output against the recorded one.
+pub fn play_back(trace: &Trace,
+                 molds: &[&Mold],
+                 params: &[Params])
+    -> Result<Vec<Difference>, Error>
+{
+    let input = trace.input();
+    let mut connection = io::Connection::with(input.as_bytes(), Vec::new());
+    let budget = Duration::from_millis(DEFAULT_TURN_BUDGET_MS);
+    try!(run(&mut connection, molds, params, budget, &mut Log::none()));
+    let (_, output) = connection.into_inner();
+    let replayed = String::from_utf8_lossy(&output)
+                       .lines()
+                       .map(|l| l.to_owned())
+                       .collect::<Vec<_>>();
+    trace::compare(&trace.sent, &replayed).map_err(Error::from)
+}
+
+/// Drives the chain of brains through the given connection until the other
+/// end hangs up.  The first brain of the chain makes the moves; the others
+/// stand in for it on the turns it panics or misses the deadline.  The
+/// chain is given `budget` worth of time for each turn.
+pub fn run<R, W>(connection: &mut io::Connection<R, W>,
+                 molds: &[&Mold],
+                 params: &[Params],
+                 budget: Duration,
+                 log: &mut Log)
+    -> Result<(), Error>
+    where R: BufRead,
+          W: Write
+{
+    let environment = try!(connection.recv_environment());
+    let mut state_frame = State::for_environment(&environment);
+    try!(connection.recv_state(&mut state_frame));
+    let my_tag = environment.my_tag;
+    log.begin_turn(state_frame.turn);
+    log.info(format_args!("Player {} on a {}x{} map for {} turns",
+                          my_tag,
+                          environment.space.width(),
+                          environment.space.height(),
+                          environment.total_turns));
+    let mut chain = reanimate_chain(molds,
+                                    params,
+                                    &environment,
+                                    &state_frame,
+                                    log);
+    log.flush();
+    if chain.is_empty() {
+        return Err(Error::NoBrain);
+    }
+    let name = &format!("UA_{}", molds[0].name());
+    try!(connection.send_ready(&my_tag, name));
+    loop {
+        match connection.recv_state(&mut state_frame) {
+            Ok(()) => (),
+            Err(io::Error::Disconnected) => return Ok(()),
+            Err(e) => return Err(Error::from(e)),
+        }
+        // The frames carry no turn number so the turns are counted here the
+        // way the environment counts them: the first move is made on turn 1
+        // and the last one on turn `total_turns`.
+        state_frame.turn += 1;
+        let deadline = Deadline::starting_now(budget);
+        log.begin_turn(state_frame.turn);
+        let actions = tick_chain(&mut chain, &state_frame, &deadline, log);
+        if log.enabled(Level::Info) {
+            let elapsed = deadline.elapsed();
+            log.info(format_args!("{} actions in {} ms",
+                                  actions.len(),
+                                  elapsed.as_secs() * 1000 +
+                                  elapsed.subsec_nanos() as u64 / 1_000_000));
+        }
+        log.flush();
+        try!(connection.send_actions(actions.iter()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::borrow::Cow;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use action::Action;
+    use brain::simple::SimpleMold;
+    use coord::Coord;
+    use deadline::Deadline;
+    use io::Connection;
+    use log::Log;
+    use params::Params;
+    use world::{Environment, State};
+    use super::{Brain, Link, Mold, run, tick_chain};
+
+    struct PanicMold;
+
+    impl Mold for PanicMold
+    {
+        fn name(&self) -> Cow<str>
+        {
+            Cow::Borrowed(&"Panic")
+        }
+
+        fn reanimate(&self,
+                     _: &Params,
+                     _: Environment,
+                     _: &State,
+                     _: &mut Log)
+            -> Box<Brain>
+        {
+            Box::new(PanicBrain)
+        }
+    }
+
+    struct PanicBrain;
+
+    impl Brain for PanicBrain
+    {
+        fn tick(&mut self, _: &State, _: &Deadline, _: &mut Log) -> Vec<Action>
+        {
+            panic!("on purpose");
+        }
+    }
+
+    /// Comes up with the given number of moves.
+    struct MovesBrain(usize);
+
+    impl Brain for MovesBrain
+    {
+        fn tick(&mut self, _: &State, _: &Deadline, _: &mut Log) -> Vec<Action>
+        {
+            vec![(Coord { x: 0, y: 0 }, None); self.0]
+        }
+    }
+
+    /// Writes down the turns its brain is ticked on.
+    struct TurnMold(Rc<RefCell<Vec<u32>>>);
+
+    impl Mold for TurnMold
+    {
+        fn name(&self) -> Cow<str>
+        {
+            Cow::Borrowed(&"Turn")
+        }
+
+        fn reanimate(&self,
+                     _: &Params,
+                     _: Environment,
+                     _: &State,
+                     _: &mut Log)
+            -> Box<Brain>
+        {
+            Box::new(TurnBrain(self.0.clone()))
+        }
+    }
+
+    struct TurnBrain(Rc<RefCell<Vec<u32>>>);
+
+    impl Brain for TurnBrain
+    {
+        fn tick(&mut self, state: &State, _: &Deadline, _: &mut Log)
+            -> Vec<Action>
+        {
+            self.0.borrow_mut().push(state.turn);
+            vec![]
+        }
+    }
+
+    const INPUT: &'static str = "1\n2 1\n1 1\n1 1 1 0 5 0\n1 1 1 0 5 0\n";
+
+    #[test]
+    fn test_run_until_disconnected()
+    {
+        let mut connection = Connection::with(INPUT.as_bytes(), Vec::new());
+        run(&mut connection,
+            &[&SimpleMold],
+            &[Params::new()],
+            Duration::from_secs(1),
+            &mut Log::none())
+            .unwrap();
+        let (_, output) = connection.into_inner();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "UA_Simple_1\n0 0 2\n");
+    }
+
+    #[test]
+    fn test_counts_turns_from_one()
+    {
+        let input = format!("{}1 1 1 0 5 0\n", INPUT);
+        let mut connection = Connection::with(input.as_bytes(), Vec::new());
+        let turns = Rc::new(RefCell::new(Vec::new()));
+        run(&mut connection,
+            &[&TurnMold(turns.clone())],
+            &[Params::new()],
+            Duration::from_secs(1),
+            &mut Log::none())
+            .unwrap();
+        assert_eq!(*turns.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_falls_back_when_brain_panics()
+    {
+        let mut connection = Connection::with(INPUT.as_bytes(), Vec::new());
+        run(&mut connection,
+            &[&PanicMold, &SimpleMold],
+            &[Params::new(), Params::new()],
+            Duration::from_secs(1),
+            &mut Log::none())
+            .unwrap();
+        let (_, output) = connection.into_inner();
+        assert_eq!(String::from_utf8(output).unwrap(), "UA_Panic_1\n0 0 2\n");
+    }
+
+    fn link(name: &str, brain: Box<Brain>) -> Link
+    {
+        Link {
+            name: name.to_owned(),
+            brain: brain,
+        }
+    }
+
+    /// A deadline of `budget` seconds that started ten seconds ago.
+    fn started_ago(budget: u64) -> Deadline
+    {
+        Deadline::new(Instant::now() - Duration::from_secs(10),
+                      Duration::from_secs(budget))
+    }
+
+    #[test]
+    fn test_keeps_late_moves()
+    {
+        let state = State {
+            turn: 1,
+            occupation_map: Vec::new(),
+        };
+        let chain = || {
+            vec![link("One", Box::new(MovesBrain(1))),
+                 link("Two", Box::new(MovesBrain(2)))]
+        };
+        // The primary misses its shortened deadline of 9 s but not the
+        // budget of 12 s
+        let actions = tick_chain(&mut chain(),
+                                 &state,
+                                 &started_ago(12),
+                                 &mut Log::none());
+        assert_eq!(actions.len(), 1);
+        // The budget has run out and the moves of the primary are all
+        // there is
+        let actions = tick_chain(&mut chain(),
+                                 &state,
+                                 &started_ago(4),
+                                 &mut Log::none());
+        assert_eq!(actions.len(), 1);
+    }
+
+    #[test]
+    fn test_stops_falling_back_once_budget_is_spent()
+    {
+        let state = State {
+            turn: 1,
+            occupation_map: Vec::new(),
+        };
+        let chain = || {
+            vec![link("Panic", Box::new(PanicBrain)),
+                 link("Two", Box::new(MovesBrain(2)))]
+        };
+        let actions = tick_chain(&mut chain(),
+                                 &state,
+                                 &started_ago(4),
+                                 &mut Log::none());
+        assert!(actions.is_empty());
+        let actions = tick_chain(&mut chain(),
+                                 &state,
+                                 &started_ago(12),
+                                 &mut Log::none());
+        assert_eq!(actions.len(), 2);
+    }
+}
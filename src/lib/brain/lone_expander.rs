@@ -18,17 +18,16 @@
 use std::borrow::Cow;
 use std::f32;
 
-use ua::{Action, Dir, Environment, Frame, Occupation, Point, State, Tag};
-use ua::util::f32_cmp;
-
+use action::Action;
 use brain::{Brain, Mold};
-use params::Params;
-
-const DEFAULT_DISCOUNT_FACTOR: f32 = 0.55;
-const DEFAULT_EXPANSION_WEIGHT: f32 = 80.0;
-const DEFAULT_AGGRESSION_WEIGHT: f32 = 50.0;
-const DEFAULT_DENSITY_WEIGHT: f32 = 70.0;
-const DEFAULT_MINIMUM_MOVABLE_STRENGTH: f32 = 40.0;
+use deadline::Deadline;
+use dir::Dir;
+use log::{Level, Log};
+use params::{Params, Schema};
+use space::frame::Frame;
+use space::point::Point;
+use util::f32_cmp;
+use world::{Environment, Occupation, State, Tag};
 
 const MAX_STR: f32 = 255.0;
 
@@ -63,25 +62,53 @@ impl Mold for LoneMold
         Cow::Borrowed(&"LoneExpander")
     }
 
+    fn schema(&self) -> Schema
+    {
+        Schema::new()
+            .param("aggression_weight",
+                   50.0,
+                   0.0,
+                   100.0,
+                   "Value of moving towards the enemies")
+            .param("density_weight",
+                   70.0,
+                   0.0,
+                   100.0,
+                   "Cost of moving into crowded areas")
+            .param("discount_factor",
+                   0.55,
+                   0.0,
+                   0.99,
+                   "Discount per step of distance")
+            .param("expansion_weight",
+                   80.0,
+                   0.0,
+                   100.0,
+                   "Value of moving towards unowned production")
+            .param("minimum_movable_strength",
+                   40.0,
+                   0.0,
+                   150.0,
+                   "Strength below which a piece does not move within own \
+                    territory")
+    }
+
     fn reanimate(&self,
                  params: &Params,
                  environment: Environment,
-                 _: &State)
+                 _: &State,
+                 _: &mut Log)
         -> Box<Brain>
     {
+        let schema = self.schema();
         Box::new(LoneBrain {
             environment: environment,
-            aggression_weight: *params.get("aggression_weight")
-                                      .unwrap_or(&DEFAULT_AGGRESSION_WEIGHT),
-            density_weight: *params.get("density_weight")
-                                   .unwrap_or(&DEFAULT_DENSITY_WEIGHT),
-            discount_factor: *params.get("discount_factor")
-                                    .unwrap_or(&DEFAULT_DISCOUNT_FACTOR),
-            expansion_weight: *params.get("expansion_weight")
-                                     .unwrap_or(&DEFAULT_EXPANSION_WEIGHT),
+            aggression_weight: schema.value(params, "aggression_weight"),
+            density_weight: schema.value(params, "density_weight"),
+            discount_factor: schema.value(params, "discount_factor"),
+            expansion_weight: schema.value(params, "expansion_weight"),
             minimum_movable_strength:
-                *params.get("minimum_movable_strength")
-                       .unwrap_or(&DEFAULT_MINIMUM_MOVABLE_STRENGTH),
+                schema.value(params, "minimum_movable_strength"),
         })
     }
 }
@@ -179,7 +206,8 @@ impl LoneBrain
                           state: &State,
                           densities: &Vec<f32>,
                           ownerships: &Vec<f32>,
-                          blood: &Vec<f32>)
+                          blood: &Vec<f32>,
+                          log: &mut Log)
         -> Action
     {
         let productions = &self.environment.production_map;
@@ -241,6 +269,15 @@ impl LoneBrain
             utilities.push((u, Some(*d)));
         }
         utilities.sort_by(|a, b| f32_cmp(&b.0, &a.0));
+        if log.enabled(Level::Debug) {
+            log.debug(format_args!("{:?} with strength {} moves {:?}",
+                                   loc.coord(),
+                                   o_src.strength,
+                                   utilities[0].1));
+            for &(u, ref dir) in utilities.iter() {
+                log.trace(format_args!("  {:?}: {}", dir, u));
+            }
+        }
         assert!(utilities[0].0.is_finite());
         (loc.coord(), utilities[0].1)
     }
@@ -254,7 +291,11 @@ impl LoneBrain
 
 impl Brain for LoneBrain
 {
-    fn tick(&mut self, state: &State) -> Vec<Action>
+    fn tick(&mut self,
+            state: &State,
+            deadline: &Deadline,
+            log: &mut Log)
+        -> Vec<Action>
     {
         let densities = self.calc_density_map(self.me(), &state.occupation_map);
         let ownerships = self.calc_ownership_map(self.me(),
@@ -267,6 +308,12 @@ impl Brain for LoneBrain
         for f in self.environment
                      .space
                      .points() {
+            // Out of time; the cells not yet decided stay still.
+            if deadline.has_passed() {
+                log.warn(format_args!("Out of time after deciding {} cells",
+                                      actions.len()));
+                break;
+            }
             let source = f.ref_on(&state.occupation_map);
             if source.tag == self.me() {
                 actions.push(self.select_cell_action(self.me(),
@@ -274,7 +321,8 @@ impl Brain for LoneBrain
                                                      state,
                                                      &densities,
                                                      &ownerships,
-                                                     &blood));
+                                                     &blood,
+                                                     log));
             }
         }
         actions
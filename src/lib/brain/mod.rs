@@ -0,0 +1,79 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The brains of the bot and the registry they are picked from by name.
+//!
+//! A `Mold` reanimates a `Brain` for a game and the brain decides the
+//! moves turn by turn.  The bot drives a chain of brains through the
+//! standard input and output with `run_forever` but the brains can just as
+//! well be ticked directly, say by a simulator or a benchmark.
+
+pub mod driver;
+pub mod lone_expander;
+pub mod probe;
+pub mod simple;
+pub mod teddy;
+
+pub use brain::driver::{Brain, DEFAULT_TURN_BUDGET_MS, Mold, panic_message,
+                        play_back, run, run_forever};
+
+/// The brains by name, each with a function making its mold.
+pub const REGISTRY: &'static [(&'static str, fn() -> Box<Mold>)] =
+    &[("lone_expander", || Box::new(lone_expander::LoneMold)),
+      ("probe", || Box::new(probe::ProbeMold)),
+      ("simple", || Box::new(simple::SimpleMold)),
+      ("teddy", || Box::new(teddy::TeddyMold))];
+
+/// The names of the brains in the registry.
+pub fn names() -> Vec<&'static str>
+{
+    REGISTRY.iter().map(|&(name, _)| name).collect()
+}
+
+/// The name of the brain played when none is asked for.
+pub fn default_name() -> &'static str
+{
+    if cfg!(feature = "probe") {
+        "probe"
+    } else {
+        "lone_expander"
+    }
+}
+
+/// Looks up the mold of the brain called `name`.
+pub fn mold_of(name: &str) -> Result<Box<Mold>, String>
+{
+    REGISTRY.iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, mold)| mold())
+            .ok_or_else(|| format!("no brain with name '{}'", name))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{mold_of, names};
+
+    #[test]
+    fn test_mold_of()
+    {
+        for name in names() {
+            assert!(mold_of(name).is_ok());
+        }
+        assert!(mold_of("nobody").is_err());
+    }
+}
@@ -19,10 +19,14 @@ use std::borrow::Cow;
 use std::num::Wrapping;
 
 use rand::{self, Rng};
-use ua::{Action, Coord, Environment, State};
 
+use action::Action;
 use brain::{Brain, Mold};
+use coord::Coord;
+use deadline::Deadline;
+use log::Log;
 use params::Params;
+use world::{Environment, State};
 
 const ALPHA: f64 = 1000.0;
 const BETA: f64 = 0.125;
@@ -36,11 +40,11 @@ impl Mold for ProbeMold
         Cow::Borrowed(&"Probe")
     }
 
-
     fn reanimate(&self,
                  _params: &Params,
                  _environment: Environment,
-                 _init_state: &State)
+                 _init_state: &State,
+                 _log: &mut Log)
         -> Box<Brain>
     {
         Box::new(ProbeBrain { iteration: 0 })
@@ -54,10 +58,17 @@ pub struct ProbeBrain
 
 impl Brain for ProbeBrain
 {
-    fn tick(&mut self, _state: &State) -> Vec<Action>
+    fn tick(&mut self,
+            _state: &State,
+            _deadline: &Deadline,
+            log: &mut Log)
+        -> Vec<Action>
     {
         self.iteration += 1;
         let work_load = (ALPHA * (self.iteration as f64 * BETA).exp()) as usize;
+        log.info(format_args!("Iteration {} with work load {}",
+                              self.iteration,
+                              work_load));
         let mut path = Wrapping(0);
         let mut rng = rand::thread_rng();
         for _ in 0..work_load {
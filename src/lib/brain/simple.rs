@@ -17,10 +17,15 @@
 
 use std::borrow::Cow;
 
-use ua::{Action, Dir, Environment, Frame, Occupation, Point, State};
-
-use params::Params;
+use action::Action;
 use brain::{Brain, Mold};
+use deadline::Deadline;
+use dir::Dir;
+use log::Log;
+use params::Params;
+use space::frame::Frame;
+use space::point::Point;
+use world::{Environment, Occupation, State};
 
 pub struct SimpleMold;
 
@@ -34,7 +39,8 @@ impl Mold for SimpleMold
     fn reanimate(&self,
                  _params: &Params,
                  environment: Environment,
-                 _init_state: &State)
+                 _init_state: &State,
+                 _log: &mut Log)
         -> Box<Brain>
     {
         Box::new(SimpleBrain { environment: environment })
@@ -71,7 +77,11 @@ fn tick_site(origin: &Point,
 
 impl Brain for SimpleBrain
 {
-    fn tick(&mut self, state: &State) -> Vec<Action>
+    fn tick(&mut self,
+            state: &State,
+            _deadline: &Deadline,
+            log: &mut Log)
+        -> Vec<Action>
     {
         let me = self.environment.my_tag;
         let mut actions = vec![];
@@ -82,6 +92,7 @@ impl Brain for SimpleBrain
                                              source,
                                              &state.occupation_map,
                                              me) {
+                    log.debug(format_args!("{:?} moves {:?}", f.coord(), dir));
                     actions.push((f.coord(), Some(dir)));
                 }
             }
@@ -17,11 +17,17 @@
 
 use std::borrow::Cow;
 
-use ua::{Action, Choice, Economic, Environment, Frame, Mask, Occupation,
-         Point, Production, Space, State, Tag, Wave};
-
+use action::{Action, Choice};
 use brain::{Brain, Mold};
+use deadline::Deadline;
+use log::Log;
+use math::Economic;
 use params::Params;
+use space::{Space, Wave};
+use space::frame::Frame;
+use space::mask::Mask;
+use space::point::Point;
+use world::{Environment, Occupation, Production, State, Tag};
 
 pub struct TeddyMold;
 
@@ -30,7 +36,8 @@ impl Mold for TeddyMold
     fn reanimate(&self,
                  _params: &Params,
                  environment: Environment,
-                 _init_state: &State)
+                 _init_state: &State,
+                 _log: &mut Log)
         -> Box<Brain>
     {
         Box::new(TeddyBrain { environment: environment })
@@ -104,7 +111,6 @@ fn compute_outward_utility<F>(source: &F,
           .sum()
 }
 
-
 /// Computes the expected utilities for conquering any of the cells belonging
 /// to the rim of foreign or unoccupied cells immediately surroinding the body
 /// of the bot.
@@ -231,10 +237,15 @@ fn select_actions(space: &Space,
 
 impl Brain for TeddyBrain
 {
-    fn tick(&mut self, state: &State) -> Vec<Action>
+    fn tick(&mut self,
+            state: &State,
+            _deadline: &Deadline,
+            _log: &mut Log)
+        -> Vec<Action>
     {
-        debug_assert!(state.turn <= self.environment.total_turns);
-        let turns_left = (self.environment.total_turns - state.turn) as i32;
+        let turns_left = self.environment
+                             .total_turns
+                             .saturating_sub(state.turn) as i32;
         let me = self.environment.my_tag;
         let my_body = Mask::create(&self.environment.space, |z: &Point| {
             z.ref_on(&state.occupation_map).tag == me
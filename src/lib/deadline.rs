@@ -0,0 +1,108 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Time budgets of the turns.
+
+use std::time::{Duration, Instant};
+
+/// The point in time by which a turn has to be completed.
+#[derive(Clone, Debug)]
+pub struct Deadline
+{
+    start: Instant,
+    budget: Duration,
+}
+
+impl Deadline
+{
+    pub fn new(start: Instant, budget: Duration) -> Self
+    {
+        Deadline {
+            start: start,
+            budget: budget,
+        }
+    }
+
+    pub fn starting_now(budget: Duration) -> Self
+    {
+        Deadline::new(Instant::now(), budget)
+    }
+
+    /// A deadline that, for any practical purpose, never passes.
+    pub fn never() -> Self
+    {
+        Deadline::starting_now(Duration::from_secs(::std::u32::MAX as u64))
+    }
+
+    pub fn budget(&self) -> Duration
+    {
+        self.budget
+    }
+
+    pub fn elapsed(&self) -> Duration
+    {
+        self.start.elapsed()
+    }
+
+    pub fn remaining(&self) -> Duration
+    {
+        let elapsed = self.elapsed();
+        if elapsed < self.budget {
+            self.budget - elapsed
+        } else {
+            Duration::new(0, 0)
+        }
+    }
+
+    pub fn has_passed(&self) -> bool
+    {
+        self.elapsed() >= self.budget
+    }
+
+    /// Returns a deadline that comes `reserve` earlier than this one.
+    pub fn shortened_by(&self, reserve: Duration) -> Deadline
+    {
+        Deadline {
+            start: self.start,
+            budget: if reserve < self.budget {
+                self.budget - reserve
+            } else {
+                Duration::new(0, 0)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::time::Duration;
+
+    use super::Deadline;
+
+    #[test]
+    fn test_budgeting()
+    {
+        let deadline = Deadline::starting_now(Duration::from_millis(500));
+        assert!(!deadline.has_passed());
+        assert!(deadline.remaining() <= Duration::from_millis(500));
+        assert!(!Deadline::never().has_passed());
+        let passed = deadline.shortened_by(Duration::from_secs(1));
+        assert!(passed.has_passed());
+        assert_eq!(passed.remaining(), Duration::new(0, 0));
+    }
+}
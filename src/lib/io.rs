@@ -15,17 +15,21 @@
 // You should have received a copy of the GNU General Public License along
 // with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::io::{self, BufRead, Stdin, Stdout, Write};
+use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
 
 use action::Action;
+use coord::Coord;
 use dir::Dir;
 use world::{Environment, Production, State, Strength, Tag};
 
-pub struct Connection
+/// Connection to the game environment over a pair of line oriented streams.
+pub struct Connection<R, W>
 {
-    input: Stdin,
-    output: Stdout,
+    input: R,
+    output: W,
     buffer: String,
+    // Receives a copy of every line that goes through the connection
+    trace: Option<Box<Write>>,
 }
 
 #[derive(Debug)]
@@ -33,6 +37,8 @@ pub enum Error
 {
     ParseError(String),
     IoError(io::Error),
+    // The environment closed the input stream
+    Disconnected,
 }
 
 impl From<io::Error> for Error
@@ -43,20 +49,66 @@ impl From<io::Error> for Error
     }
 }
 
-impl Connection
+impl Connection<BufReader<Stdin>, Stdout>
 {
+    /// Connects to the environment through the standard input and output.
     pub fn new() -> Self
+    {
+        Connection::with(BufReader::new(io::stdin()), io::stdout())
+    }
+}
+
+impl<R, W> Connection<R, W>
+    where R: BufRead,
+          W: Write
+{
+    pub fn with(input: R, output: W) -> Self
     {
         Connection {
-            input: io::stdin(),
-            output: io::stdout(),
+            input: input,
+            output: output,
             buffer: String::new(),
+            trace: None,
         }
     }
+
+    /// Copies every line received and sent into `trace`.  The received lines
+    /// are prefixed with "< " and the sent ones with "> ".
+    pub fn trace_to(&mut self, trace: Box<Write>)
+    {
+        self.trace = Some(trace);
+    }
+
+    pub fn into_inner(self) -> (R, W)
+    {
+        (self.input, self.output)
+    }
+
     fn recv_string(&mut self) -> Result<usize, Error>
     {
         self.buffer.clear();
-        self.input.lock().read_line(&mut self.buffer).map_err(Error::from)
+        let n = match self.input.read_line(&mut self.buffer) {
+            Ok(0) => return Err(Error::Disconnected),
+            Ok(n) => n,
+            Err(e) => return Err(Error::from(e)),
+        };
+        if let Some(ref mut trace) = self.trace {
+            try!(write!(trace, "< {}", self.buffer));
+            if !self.buffer.ends_with('\n') {
+                try!(write!(trace, "\n"));
+            }
+        }
+        Ok(n)
+    }
+    fn send_string(&mut self) -> Result<(), Error>
+    {
+        try!(write!(self.output, "{}\n", self.buffer));
+        try!(self.output.flush());
+        if let Some(ref mut trace) = self.trace {
+            try!(write!(trace, "> {}\n", self.buffer));
+            try!(trace.flush());
+        }
+        Ok(())
     }
     fn parse_err(&self, message: &str) -> Error
     {
@@ -147,27 +199,107 @@ impl Connection
     }
     pub fn send_ready(&mut self, my_tag: &Tag, name: &str) -> Result<(), Error>
     {
-        write!(self.output.lock(), "{}_{}\n", name, my_tag).map_err(Error::from)
+        self.buffer = format!("{}_{}", name, my_tag);
+        self.send_string()
     }
     pub fn send_actions<'a, I>(&mut self, moves: I) -> Result<(), Error>
         where I: Iterator<Item = &'a Action>
     {
-        let mut count = 0;
-        let mut output_handle = self.output.lock();
+        use std::fmt::Write;
+        self.buffer.clear();
         for &(ref pos, ref dir) in moves {
-            let encoded_dir = match *dir {
-                None => 0,
-                Some(Dir::North) => 1,
-                Some(Dir::East) => 2,
-                Some(Dir::South) => 3,
-                Some(Dir::West) => 4,
-            };
-            if count > 0 {
-                try!(write!(output_handle, " "));
+            if !self.buffer.is_empty() {
+                self.buffer.push(' ');
             }
-            try!(write!(output_handle, "{} {} {}", pos.x, pos.y, encoded_dir));
-            count += 1;
+            write!(self.buffer, "{} {} {}", pos.x, pos.y, encode_dir(dir))
+                .unwrap();
         }
-        write!(output_handle, "\n").map_err(Error::from)
+        self.send_string()
+    }
+}
+
+fn encode_dir(dir: &Option<Dir>) -> u8
+{
+    match *dir {
+        None => 0,
+        Some(Dir::North) => 1,
+        Some(Dir::East) => 2,
+        Some(Dir::South) => 3,
+        Some(Dir::West) => 4,
+    }
+}
+
+/// Decodes the actions from a message sent by a bot.
+pub fn decode_actions(message: &str) -> Result<Vec<Action>, Error>
+{
+    let err = || Error::ParseError(format!("bad actions: {:?}", message));
+    let mut parts = message.split_whitespace();
+    let mut actions = Vec::new();
+    while let Some(x) = parts.next() {
+        let x = try!(x.parse::<i16>().map_err(|_| err()));
+        let y = try!(parts.next()
+                          .ok_or(err())
+                          .and_then(|y| y.parse::<i16>().map_err(|_| err())));
+        let dir = match parts.next() {
+            Some("0") => None,
+            Some("1") => Some(Dir::North),
+            Some("2") => Some(Dir::East),
+            Some("3") => Some(Dir::South),
+            Some("4") => Some(Dir::West),
+            _ => return Err(err()),
+        };
+        actions.push((Coord { x: x, y: y }, dir));
+    }
+    Ok(actions)
+}
+
+#[cfg(test)]
+mod test {
+
+    use coord::Coord;
+    use dir::Dir;
+    use world::State;
+
+    use super::{Connection, Error, decode_actions};
+
+    #[test]
+    fn test_in_memory_session()
+    {
+        let input = "2\n3 2\n1 2 3 4 5 6\n2 0 1 1 3 2 0 0 0 0 0 7\n";
+        let mut connection = Connection::with(input.as_bytes(), Vec::new());
+        let environment = connection.recv_environment().unwrap();
+        assert_eq!(environment.my_tag, 2);
+        assert_eq!(environment.production_map, vec![1, 2, 3, 4, 5, 6]);
+        let mut state = State::for_environment(&environment);
+        connection.recv_state(&mut state).unwrap();
+        let tags = state.occupation_map
+                        .iter()
+                        .map(|o| o.tag)
+                        .collect::<Vec<_>>();
+        assert_eq!(tags, vec![0, 0, 1, 2, 2, 2]);
+        assert_eq!(state.occupation_map[5].strength, 7);
+        match connection.recv_state(&mut state) {
+            Err(Error::Disconnected) => (),
+            other => panic!("expected disconnection, got {:?}", other),
+        }
+        connection.send_ready(&2, "Foo").unwrap();
+        connection.send_actions([(Coord { x: 1, y: 1 }, Some(Dir::West)),
+                                 (Coord { x: 2, y: 1 }, None)]
+                                    .iter())
+                  .unwrap();
+        let (_, output) = connection.into_inner();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "Foo_2\n1 1 4 2 1 0\n");
+    }
+
+    #[test]
+    fn test_decode_actions()
+    {
+        assert_eq!(decode_actions("1 1 4 2 1 0").unwrap(),
+                   vec![(Coord { x: 1, y: 1 }, Some(Dir::West)),
+                        (Coord { x: 2, y: 1 }, None)]);
+        assert_eq!(decode_actions("").unwrap(), vec![]);
+        assert!(decode_actions("1 1").is_err());
+        assert!(decode_actions("1 1 5").is_err());
     }
 }
@@ -0,0 +1,373 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Just enough JSON to deal with the files of the Halite environment.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::Chars;
+use std::iter::Peekable;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value
+{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+#[derive(Debug)]
+pub struct Error
+{
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl Value
+{
+    pub fn get(&self, key: &str) -> Option<&Value>
+    {
+        match *self {
+            Value::Object(ref fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64>
+    {
+        match *self {
+            Value::Number(n) if n.fract() == 0.0 => Some(n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str>
+    {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>>
+    {
+        match *self {
+            Value::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Writes `s` as a quoted JSON string.
+pub fn write_str(f: &mut fmt::Write, s: &str) -> fmt::Result
+{
+    try!(f.write_char('"'));
+    for c in s.chars() {
+        match c {
+            '"' => try!(f.write_str("\\\"")),
+            '\\' => try!(f.write_str("\\\\")),
+            '\n' => try!(f.write_str("\\n")),
+            '\r' => try!(f.write_str("\\r")),
+            '\t' => try!(f.write_str("\\t")),
+            c if (c as u32) < 0x20 => {
+                try!(write!(f, "\\u{:04x}", c as u32))
+            }
+            c => try!(f.write_char(c)),
+        }
+    }
+    f.write_char('"')
+}
+
+impl fmt::Display for Value
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            // JSON has no room for NaN and the infinities
+            Value::Number(n) if !n.is_finite() => write!(f, "null"),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(ref s) => write_str(f, s),
+            Value::Array(ref items) => {
+                try!(write!(f, "["));
+                for (ix, item) in items.iter().enumerate() {
+                    if ix > 0 {
+                        try!(write!(f, ","));
+                    }
+                    try!(write!(f, "{}", item));
+                }
+                write!(f, "]")
+            }
+            Value::Object(ref fields) => {
+                try!(write!(f, "{{"));
+                for (ix, (key, value)) in fields.iter().enumerate() {
+                    if ix > 0 {
+                        try!(write!(f, ","));
+                    }
+                    try!(write_str(f, key));
+                    try!(write!(f, ":{}", value));
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Parser<'a>
+{
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+}
+
+impl<'a> Parser<'a>
+{
+    fn err<T>(&self, message: &str) -> Result<T, Error>
+    {
+        Err(Error {
+            offset: self.offset,
+            message: message.to_owned(),
+        })
+    }
+
+    fn bump(&mut self) -> Option<char>
+    {
+        let c = self.chars.next();
+        if c.is_some() {
+            self.offset += 1;
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: Value) -> Result<Value, Error>
+    {
+        for expected in word.chars() {
+            if self.bump() != Some(expected) {
+                return self.err(&format!("expected '{}'", word));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Error>
+    {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('n') => self.expect_word("null", Value::Null),
+            Some('t') => self.expect_word("true", Value::Bool(true)),
+            Some('f') => self.expect_word("false", Value::Bool(false)),
+            Some('"') => self.parse_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_object(),
+            Some(c) if c == '-' || c.is_digit(10) => self.parse_number(),
+            Some(_) => self.err("unexpected character"),
+            None => self.err("unexpected end of input"),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, Error>
+    {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_digit(10) || c == '-' || c == '+' || c == '.' ||
+               c == 'e' || c == 'E' {
+                s.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        match s.parse::<f64>() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => self.err("bad number"),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error>
+    {
+        debug_assert_eq!(self.chars.peek(), Some(&'"'));
+        self.bump();
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = 0;
+                            for _ in 0..4 {
+                                let digit = self.bump()
+                                                .and_then(|c| c.to_digit(16));
+                                match digit {
+                                    Some(d) => code = 16 * code + d,
+                                    None => return self.err("bad escape"),
+                                }
+                            }
+                            // Surrogate pairs are not worth the trouble here
+                            ::std::char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => return self.err("bad escape"),
+                    };
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+                None => return self.err("unterminated string"),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Error>
+    {
+        debug_assert_eq!(self.chars.peek(), Some(&'['));
+        self.bump();
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.bump();
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(try!(self.parse_value()));
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return self.err("expected ',' or ']'"),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, Error>
+    {
+        debug_assert_eq!(self.chars.peek(), Some(&'{'));
+        self.bump();
+        let mut fields = BTreeMap::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.bump();
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() != Some(&'"') {
+                return self.err("expected field name");
+            }
+            let key = try!(self.parse_string());
+            self.skip_whitespace();
+            if self.bump() != Some(':') {
+                return self.err("expected ':'");
+            }
+            let value = try!(self.parse_value());
+            fields.insert(key, value);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(fields)),
+                _ => return self.err("expected ',' or '}'"),
+            }
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Value, Error>
+{
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+        offset: 0,
+    };
+    let value = try!(parser.parse_value());
+    parser.skip_whitespace();
+    if parser.chars.peek().is_some() {
+        parser.err("unconsumed input after value")
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{Value, parse};
+
+    #[test]
+    fn test_round_trip()
+    {
+        let input = "{\"a\":[1,-2.5,true,null],\"b\":\"x\\\"y\\n\",\"c\":{}}";
+        let value = parse(input).unwrap();
+        assert_eq!(value.get("a").unwrap().as_array().unwrap().len(), 4);
+        assert_eq!(value.get("b").unwrap().as_str(), Some("x\"y\n"));
+        assert_eq!(format!("{}", value), input);
+    }
+
+    #[test]
+    fn test_writes_non_finite_numbers_as_null()
+    {
+        let value = Value::Array(vec![Value::Number(::std::f64::NAN),
+                                      Value::Number(::std::f64::INFINITY),
+                                      Value::Number(-1.5)]);
+        let output = format!("{}", value);
+        assert_eq!(output, "[null,null,-1.5]");
+        assert!(parse(&output).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_garbage()
+    {
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("[] []").is_err());
+        assert_eq!(parse(" [ ] ").unwrap(), Value::Array(vec![]));
+    }
+}
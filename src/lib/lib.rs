@@ -15,19 +15,31 @@
 // You should have received a copy of the GNU General Public License along
 // with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
 
+extern crate rand;
+
 pub mod action;
+pub mod brain;
 pub mod coord;
+pub mod deadline;
 pub mod dir;
 pub mod io;
+pub mod json;
+pub mod log;
 pub mod map;
 pub mod math;
+pub mod params;
+pub mod replay;
+pub mod sim;
 pub mod space;
+pub mod trace;
 pub mod util;
 pub mod world;
 
 pub use action::{Action, Choice};
 pub use coord::Coord;
+pub use deadline::Deadline;
 pub use dir::Dir;
+pub use log::Log;
 pub use map::{Map, MutMap, RefMap};
 pub use math::Economic;
 pub use space::frame::Frame;
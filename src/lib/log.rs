@@ -0,0 +1,195 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Log of the internal events of a bot.
+//!
+//! The standard output is reserved for talking with the environment so the
+//! log goes to a file of its own.  The log is divided into sections, one for
+//! each turn.  Failing to write the log is never fatal; the entries are just
+//! lost.
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level
+{
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for Level
+{
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("no log level with name '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Level
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.pad(match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        })
+    }
+}
+
+pub struct Log
+{
+    sink: Option<Box<Write>>,
+    level: Level,
+}
+
+impl Log
+{
+    /// Logs the events at `level` and above into `sink`.
+    pub fn to(sink: Box<Write>, level: Level) -> Self
+    {
+        Log {
+            sink: Some(sink),
+            level: level,
+        }
+    }
+
+    /// A log that discards everything.
+    pub fn none() -> Self
+    {
+        Log {
+            sink: None,
+            level: Level::Error,
+        }
+    }
+
+    /// Tells whether the events at `level` end up in the log.  Use this to
+    /// avoid preparing expensive entries for nothing.
+    #[inline]
+    pub fn enabled(&self, level: Level) -> bool
+    {
+        self.sink.is_some() && level <= self.level
+    }
+
+    /// Starts the section of the turn `turn`.
+    pub fn begin_turn(&mut self, turn: u32)
+    {
+        if let Some(ref mut sink) = self.sink {
+            let _ = write!(sink, "== Turn {} ==\n", turn);
+        }
+    }
+
+    pub fn write(&mut self, level: Level, args: fmt::Arguments)
+    {
+        if self.enabled(level) {
+            if let Some(ref mut sink) = self.sink {
+                let _ = write!(sink, "{:<5} {}\n", level, args);
+            }
+        }
+    }
+
+    /// Flushes the log; do this at least at the end of each turn so that
+    /// not much is lost if the bot gets killed.
+    pub fn flush(&mut self)
+    {
+        if let Some(ref mut sink) = self.sink {
+            let _ = sink.flush();
+        }
+    }
+
+    pub fn error(&mut self, args: fmt::Arguments)
+    {
+        self.write(Level::Error, args)
+    }
+
+    pub fn warn(&mut self, args: fmt::Arguments)
+    {
+        self.write(Level::Warn, args)
+    }
+
+    pub fn info(&mut self, args: fmt::Arguments)
+    {
+        self.write(Level::Info, args)
+    }
+
+    pub fn debug(&mut self, args: fmt::Arguments)
+    {
+        self.write(Level::Debug, args)
+    }
+
+    pub fn trace(&mut self, args: fmt::Arguments)
+    {
+        self.write(Level::Trace, args)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use super::{Level, Log};
+
+    #[derive(Clone)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_levels_and_sections()
+    {
+        let buffer = Shared(Rc::new(RefCell::new(Vec::new())));
+        let mut log = Log::to(Box::new(buffer.clone()), Level::Info);
+        assert!(log.enabled(Level::Warn));
+        assert!(!log.enabled(Level::Debug));
+        log.begin_turn(3);
+        log.warn(format_args!("low on {}", "time"));
+        log.debug(format_args!("not shown"));
+        log.flush();
+        assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(),
+                   "== Turn 3 ==\nWARN  low on time\n");
+        assert!(!Log::none().enabled(Level::Error));
+    }
+}
@@ -0,0 +1,442 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tunable parameters of the brains.
+//!
+//! Each brain declares the parameters it understands with a `Schema`.  The
+//! bot checks the parameters given to it against the schema and the runner
+//! samples new parameter sets from it.  The textual form of the schema, one
+//! parameter per line, is how the schema travels from the bot to the runner.
+//!
+//! Parameter sets can be stored in a `ParamFile`:
+//!
+//! ```text
+//! # Applies to every brain that understands the parameter
+//! discount_factor = 0.6
+//!
+//! [lone_expander]
+//! aggression_weight = 42
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+
+pub type Params = BTreeMap<String, f32>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamSpec
+{
+    pub name: String,
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error
+{
+    Unknown(String),
+    OutOfRange {
+        name: String,
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+    Format(String),
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    Io(String),
+}
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self {
+            Error::Unknown(ref name) => {
+                write!(f, "unknown parameter '{}'", name)
+            }
+            Error::OutOfRange { ref name, value, min, max } => {
+                write!(f,
+                       "parameter '{}' is {} but should be between {} and {}",
+                       name,
+                       value,
+                       min,
+                       max)
+            }
+            Error::Format(ref line) => {
+                write!(f, "bad parameter specification '{}'", line)
+            }
+            Error::Syntax { line, ref message } => {
+                write!(f, "line {}: {}", line, message)
+            }
+            Error::Io(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema
+{
+    specs: Vec<ParamSpec>,
+}
+
+impl Schema
+{
+    pub fn new() -> Self
+    {
+        Schema { specs: Vec::new() }
+    }
+
+    /// Declares the parameter `name` that takes values from `min` to `max`
+    /// inclusive and is `default` when not given.
+    pub fn param(mut self,
+                 name: &str,
+                 default: f32,
+                 min: f32,
+                 max: f32,
+                 description: &str)
+        -> Self
+    {
+        assert!(min <= default && default <= max);
+        self.specs.push(ParamSpec {
+            name: name.to_owned(),
+            default: default,
+            min: min,
+            max: max,
+            description: description.to_owned(),
+        });
+        self
+    }
+
+    pub fn specs(&self) -> &[ParamSpec]
+    {
+        &self.specs
+    }
+
+    pub fn spec(&self, name: &str) -> Option<&ParamSpec>
+    {
+        self.specs.iter().find(|s| s.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.specs.is_empty()
+    }
+
+    /// Adds the parameters of `other` not yet declared by this schema.
+    pub fn merge(&mut self, other: &Schema)
+    {
+        for spec in other.specs.iter() {
+            if self.spec(&spec.name).is_none() {
+                self.specs.push(spec.clone());
+            }
+        }
+    }
+
+    /// Checks that every parameter in `params` is declared and in range.
+    pub fn check(&self, params: &Params) -> Result<(), Error>
+    {
+        for (name, &value) in params.iter() {
+            match self.spec(name) {
+                Some(spec) => {
+                    if !(spec.min <= value && value <= spec.max) {
+                        return Err(Error::OutOfRange {
+                            name: name.clone(),
+                            value: value,
+                            min: spec.min,
+                            max: spec.max,
+                        });
+                    }
+                }
+                None => return Err(Error::Unknown(name.clone())),
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of the parameter `name` in `params` or its default.  Panics
+    /// if the schema does not declare the parameter.
+    pub fn value(&self, params: &Params, name: &str) -> f32
+    {
+        match params.get(name) {
+            Some(&value) => value,
+            None => {
+                self.spec(name)
+                    .map(|s| s.default)
+                    .expect("parameter not declared in schema")
+            }
+        }
+    }
+
+    /// Draws a value for each parameter uniformly from its range.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Params
+    {
+        self.specs
+            .iter()
+            .map(|s| {
+                let value = if s.min < s.max {
+                    Range::new(s.min, s.max).ind_sample(rng)
+                } else {
+                    s.min
+                };
+                (s.name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Parses the textual form written by `Display`.
+    pub fn parse(s: &str) -> Result<Schema, Error>
+    {
+        let mut schema = Schema::new();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.splitn(5, ' ');
+            let mut next = || fields.next().unwrap_or("");
+            let name = next().to_owned();
+            let numbers = [next(), next(), next()];
+            let description = next().to_owned();
+            let mut values = [0.0; 3];
+            for (v, n) in values.iter_mut().zip(numbers.iter()) {
+                *v = try!(n.parse::<f32>()
+                           .map_err(|_| Error::Format(line.to_owned())));
+            }
+            if name.is_empty() ||
+               !(values[1] <= values[0] && values[0] <= values[2]) {
+                return Err(Error::Format(line.to_owned()));
+            }
+            schema = schema.param(&name,
+                                  values[0],
+                                  values[1],
+                                  values[2],
+                                  &description);
+        }
+        Ok(schema)
+    }
+}
+
+impl fmt::Display for Schema
+{
+    /// Writes one line per parameter: the name, the default, the minimum,
+    /// the maximum and the description separated by spaces.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        for s in self.specs.iter() {
+            try!(write!(f,
+                        "{} {} {} {} {}\n",
+                        s.name,
+                        s.default,
+                        s.min,
+                        s.max,
+                        s.description));
+        }
+        Ok(())
+    }
+}
+
+/// Parameter values read from a file: the global ones and those in the
+/// sections named after the brains.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamFile
+{
+    pub global: Params,
+    pub sections: BTreeMap<String, Params>,
+}
+
+impl ParamFile
+{
+    pub fn new() -> Self
+    {
+        ParamFile {
+            global: Params::new(),
+            sections: BTreeMap::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error>
+    {
+        let mut text = String::new();
+        try!(File::open(path)
+                 .and_then(|mut f| f.read_to_string(&mut text))
+                 .map_err(|e| Error::Io(e.to_string())));
+        ParamFile::parse(&text)
+    }
+
+    pub fn parse(s: &str) -> Result<Self, Error>
+    {
+        let mut file = ParamFile::new();
+        let mut section: Option<String> = None;
+        for (ix, line) in s.lines().enumerate() {
+            let err = |message: &str| {
+                Error::Syntax {
+                    line: ix + 1,
+                    message: message.to_owned(),
+                }
+            };
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 3 {
+                    return Err(err("bad section header"));
+                }
+                let name = line[1..line.len() - 1].trim().to_owned();
+                file.sections.entry(name.clone()).or_insert_with(Params::new);
+                section = Some(name);
+                continue;
+            }
+            let mut kv = line.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim();
+            let value = try!(kv.next()
+                               .ok_or_else(|| err("expected KEY = VALUE")));
+            let value = try!(value.trim()
+                                  .parse::<f32>()
+                                  .map_err(|_| err("bad value")));
+            if key.is_empty() {
+                return Err(err("missing key"));
+            }
+            let params = match section {
+                Some(ref name) => file.sections.get_mut(name).unwrap(),
+                None => &mut file.global,
+            };
+            params.insert(key.to_owned(), value);
+        }
+        Ok(file)
+    }
+
+    /// The parameters for the brain `name` understanding `schema`.  The
+    /// global values are overridden by those in the brain's section which
+    /// in turn are overridden by `overrides`.  The global values and the
+    /// overrides the brain does not understand are left out.
+    pub fn params_for(&self,
+                      name: &str,
+                      schema: &Schema,
+                      overrides: &Params)
+        -> Params
+    {
+        let mut params = Params::new();
+        for (k, v) in self.global.iter() {
+            if schema.spec(k).is_some() {
+                params.insert(k.clone(), *v);
+            }
+        }
+        if let Some(section) = self.sections.get(name) {
+            for (k, v) in section.iter() {
+                params.insert(k.clone(), *v);
+            }
+        }
+        for (k, v) in overrides.iter() {
+            if schema.spec(k).is_some() {
+                params.insert(k.clone(), *v);
+            }
+        }
+        params
+    }
+}
+
+impl fmt::Display for ParamFile
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        for (k, v) in self.global.iter() {
+            try!(write!(f, "{} = {}\n", k, v));
+        }
+        for (name, params) in self.sections.iter() {
+            try!(write!(f, "\n[{}]\n", name));
+            for (k, v) in params.iter() {
+                try!(write!(f, "{} = {}\n", k, v));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{SeedableRng, StdRng};
+
+    use super::{Error, ParamFile, Params, Schema};
+
+    fn schema() -> Schema
+    {
+        Schema::new()
+            .param("weight", 50.0, 0.0, 100.0, "How much it matters")
+            .param("factor", 0.5, 0.0, 0.99, "Discount")
+    }
+
+    #[test]
+    fn test_check_and_values()
+    {
+        let schema = schema();
+        let mut params = Params::new();
+        params.insert("weight".to_owned(), 20.0);
+        assert_eq!(schema.check(&params), Ok(()));
+        assert_eq!(schema.value(&params, "weight"), 20.0);
+        assert_eq!(schema.value(&params, "factor"), 0.5);
+        params.insert("factr".to_owned(), 0.1);
+        assert_eq!(schema.check(&params),
+                   Err(Error::Unknown("factr".to_owned())));
+        params.remove("factr");
+        params.insert("factor".to_owned(), 1.5);
+        assert!(schema.check(&params).is_err());
+    }
+
+    #[test]
+    fn test_text_round_trip_and_sampling()
+    {
+        let schema = schema();
+        assert_eq!(Schema::parse(&schema.to_string()), Ok(schema.clone()));
+        assert!(Schema::parse("weight 50 0").is_err());
+        let seed: &[_] = &[1, 2, 3];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        for _ in 0..10 {
+            assert_eq!(schema.check(&schema.sample(&mut rng)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_param_file()
+    {
+        let file = ParamFile::parse("# Tuned\nfactor = 0.6\nother = 1\n\n\
+                                     [lone]\nweight = 42 # by hand\n")
+                       .unwrap();
+        assert_eq!(ParamFile::parse(&file.to_string()), Ok(file.clone()));
+        let mut overrides = Params::new();
+        overrides.insert("factor".to_owned(), 0.7);
+        let params = file.params_for("lone", &schema(), &overrides);
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["weight"], 42.0);
+        assert_eq!(params["factor"], 0.7);
+        assert_eq!(file.params_for("other", &schema(), &Params::new()).len(),
+                   1);
+        assert_eq!(ParamFile::parse("[lone]\nweight 42"),
+                   Err(Error::Syntax {
+                       line: 2,
+                       message: "expected KEY = VALUE".to_owned(),
+                   }));
+    }
+}
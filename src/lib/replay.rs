@@ -0,0 +1,492 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reading and writing of the `.hlt` replay files.
+//!
+//! A replay is a JSON object holding the map dimensions, the player names,
+//! the production map and all the frames of the game, each a grid of
+//! `[owner, strength]` pairs, plus the grids of the moves made between the
+//! frames.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use action::Action;
+use dir::Dir;
+use json::{self, Value};
+use world::{Environment, Occupation, State, Tag};
+
+const VERSION: i64 = 11;
+
+#[derive(Debug)]
+pub enum Error
+{
+    Io(io::Error),
+    Json(json::Error),
+    Format(String),
+}
+
+impl From<io::Error> for Error
+{
+    fn from(e: io::Error) -> Self
+    {
+        Error::Io(e)
+    }
+}
+
+impl From<json::Error> for Error
+{
+    fn from(e: json::Error) -> Self
+    {
+        Error::Json(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Replay
+{
+    /// The environment of the game; `my_tag` is always zero.
+    pub environment: Environment,
+    pub player_names: Vec<String>,
+    pub frames: Vec<State>,
+    /// The moves made on each turn indexed by the cell.  The moves made on
+    /// `frames[t]` are in `moves[t]`.
+    pub moves: Vec<Vec<Option<Dir>>>,
+}
+
+/// How a player fared over a game, turn by turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerStats
+{
+    /// The number of cells owned on each frame
+    pub territory: Vec<usize>,
+    /// The total strength of the owned cells on each frame
+    pub strength: Vec<u32>,
+    /// The total production of the owned cells on each frame
+    pub production: Vec<u32>,
+    /// The first frame on which the player owned nothing, if any
+    pub eliminated: Option<usize>,
+}
+
+fn encode_dir(dir: &Option<Dir>) -> u8
+{
+    match *dir {
+        None => 0,
+        Some(Dir::North) => 1,
+        Some(Dir::East) => 2,
+        Some(Dir::South) => 3,
+        Some(Dir::West) => 4,
+    }
+}
+
+fn decode_dir(code: i64) -> Result<Option<Dir>, Error>
+{
+    match code {
+        0 => Ok(None),
+        1 => Ok(Some(Dir::North)),
+        2 => Ok(Some(Dir::East)),
+        3 => Ok(Some(Dir::South)),
+        4 => Ok(Some(Dir::West)),
+        _ => Err(Error::Format(format!("bad direction {}", code))),
+    }
+}
+
+fn field<'a>(root: &'a Value, key: &str) -> Result<&'a Value, Error>
+{
+    root.get(key).ok_or(Error::Format(format!("missing field '{}'", key)))
+}
+
+fn int_of(value: &Value, what: &str) -> Result<i64, Error>
+{
+    value.as_i64().ok_or(Error::Format(format!("bad {}", what)))
+}
+
+fn array_of<'a>(value: &'a Value, what: &str) -> Result<&'a Vec<Value>, Error>
+{
+    value.as_array().ok_or(Error::Format(format!("bad {}", what)))
+}
+
+/// Flattens a `height` rows by `width` columns grid into row-major order.
+fn cells_of<'a>(grid: &'a Value,
+                width: usize,
+                height: usize,
+                what: &str)
+    -> Result<Vec<&'a Value>, Error>
+{
+    let rows = try!(array_of(grid, what));
+    if rows.len() != height {
+        return Err(Error::Format(format!("{} has wrong height", what)));
+    }
+    let mut cells = Vec::with_capacity(width * height);
+    for row in rows.iter() {
+        let row = try!(array_of(row, what));
+        if row.len() != width {
+            return Err(Error::Format(format!("{} has wrong width", what)));
+        }
+        cells.extend(row.iter());
+    }
+    Ok(cells)
+}
+
+impl Replay
+{
+    /// Starts a new replay from the initial frame of a game.
+    pub fn new(environment: Environment,
+               player_names: Vec<String>,
+               init_state: State)
+        -> Self
+    {
+        Replay {
+            environment: environment,
+            player_names: player_names,
+            frames: vec![init_state],
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn n_players(&self) -> usize
+    {
+        self.player_names.len()
+    }
+
+    /// Appends a turn played with `moves` (laid out as in
+    /// `sim::resolve_turn`) that resulted in `state`.
+    pub fn record_turn(&mut self, moves: &[Vec<Action>], state: State)
+    {
+        let space = &self.environment.space;
+        let mut dirs = vec![None; space.len()];
+        {
+            let last = self.frames.last().unwrap();
+            for (p, player_moves) in moves.iter().enumerate() {
+                for &(ref coord, ref dir) in player_moves.iter() {
+                    let ix = space.ix_of(coord);
+                    if last.occupation_map[ix].tag as usize == p + 1 {
+                        dirs[ix] = dir.clone();
+                    }
+                }
+            }
+        }
+        self.moves.push(dirs);
+        self.frames.push(state);
+    }
+
+    /// Returns the actions the player `tag` took on the turn `turn`.
+    pub fn actions_of(&self, turn: usize, tag: Tag) -> Vec<Action>
+    {
+        let space = &self.environment.space;
+        self.frames[turn]
+            .occupation_map
+            .iter()
+            .enumerate()
+            .filter(|&(_, o)| o.tag == tag)
+            .map(|(ix, _)| (space.coord_of(ix), self.moves[turn][ix]))
+            .collect()
+    }
+
+    /// The statistics of each player in the order of their tags.
+    pub fn player_stats(&self) -> Vec<PlayerStats>
+    {
+        let n_frames = self.frames.len();
+        let mut stats = vec![PlayerStats {
+                                 territory: vec![0; n_frames],
+                                 strength: vec![0; n_frames],
+                                 production: vec![0; n_frames],
+                                 eliminated: None,
+                             };
+                             self.n_players()];
+        let productions = &self.environment.production_map;
+        for (t, frame) in self.frames.iter().enumerate() {
+            for (ix, o) in frame.occupation_map.iter().enumerate() {
+                if o.tag == 0 || o.tag as usize > stats.len() {
+                    continue;
+                }
+                let s = &mut stats[o.tag as usize - 1];
+                s.territory[t] += 1;
+                s.strength[t] += o.strength as u32;
+                s.production[t] += productions[ix] as u32;
+            }
+        }
+        for s in stats.iter_mut() {
+            s.eliminated = s.territory.iter().position(|&n| n == 0);
+        }
+        stats
+    }
+
+    pub fn load<P>(path: P) -> Result<Self, Error>
+        where P: AsRef<Path>
+    {
+        let file = try!(File::open(path));
+        Replay::read(file)
+    }
+
+    pub fn read<R>(mut reader: R) -> Result<Self, Error>
+        where R: Read
+    {
+        let mut input = String::new();
+        try!(reader.read_to_string(&mut input));
+        let root = try!(json::parse(&input));
+        let version = try!(int_of(try!(field(&root, "version")), "version"));
+        if version != VERSION {
+            return Err(Error::Format(format!("unsupported version {}",
+                                             version)));
+        }
+        let width = try!(int_of(try!(field(&root, "width")), "width"));
+        let height = try!(int_of(try!(field(&root, "height")), "height"));
+        if width <= 0 || height <= 0 || width > 255 || height > 255 {
+            return Err(Error::Format("bad map dimensions".to_owned()));
+        }
+        let mut environment = Environment::create(0,
+                                                  width as i16,
+                                                  height as i16);
+        let (w, h) = (width as usize, height as usize);
+        // Players
+        let mut player_names = Vec::new();
+        for name in try!(array_of(try!(field(&root, "player_names")),
+                                  "player names"))
+                        .iter() {
+            let name = try!(name.as_str()
+                                .ok_or(Error::Format("bad player name"
+                                                         .to_owned())));
+            player_names.push(name.to_owned());
+        }
+        // Production map
+        for cell in try!(cells_of(try!(field(&root, "productions")),
+                                  w,
+                                  h,
+                                  "production map")) {
+            let production = try!(int_of(cell, "production level"));
+            environment.production_map.push(production as i16);
+        }
+        // Frames
+        let mut frames = Vec::new();
+        for (turn, frame) in try!(array_of(try!(field(&root, "frames")),
+                                           "frames"))
+                                 .iter()
+                                 .enumerate() {
+            let mut state = State::for_environment(&environment);
+            state.turn = turn as u32;
+            for (cell, occupation) in try!(cells_of(frame, w, h, "frame"))
+                                          .into_iter()
+                                          .zip(state.occupation_map
+                                                    .iter_mut()) {
+                let pair = try!(array_of(cell, "site"));
+                if pair.len() != 2 {
+                    return Err(Error::Format("bad site".to_owned()));
+                }
+                let tag = try!(int_of(&pair[0], "owner"));
+                if tag < 0 || tag as usize > player_names.len() {
+                    return Err(Error::Format(format!("bad owner {}", tag)));
+                }
+                *occupation = Occupation {
+                    tag: tag as Tag,
+                    strength: try!(int_of(&pair[1], "strength")) as i16,
+                };
+            }
+            frames.push(state);
+        }
+        if frames.is_empty() {
+            return Err(Error::Format("no frames".to_owned()));
+        }
+        // Moves
+        let mut moves = Vec::new();
+        for grid in try!(array_of(try!(field(&root, "moves")), "moves"))
+                        .iter() {
+            let mut dirs = Vec::with_capacity(w * h);
+            for cell in try!(cells_of(grid, w, h, "moves")) {
+                dirs.push(try!(decode_dir(try!(int_of(cell, "move")))));
+            }
+            moves.push(dirs);
+        }
+        if moves.len() + 1 != frames.len() {
+            return Err(Error::Format("number of moves mismatches the \
+                                      number of frames"
+                                         .to_owned()));
+        }
+        Ok(Replay {
+            environment: environment,
+            player_names: player_names,
+            frames: frames,
+            moves: moves,
+        })
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<(), Error>
+        where P: AsRef<Path>
+    {
+        let mut writer = BufWriter::new(try!(File::create(path)));
+        try!(self.write(&mut writer));
+        try!(writer.flush());
+        Ok(())
+    }
+
+    pub fn write<W>(&self, writer: &mut W) -> Result<(), Error>
+        where W: Write
+    {
+        let space = &self.environment.space;
+        let (w, h) = (space.width() as usize, space.height() as usize);
+        try!(write!(writer,
+                    "{{\"version\":{},\"width\":{},\"height\":{},\
+                     \"num_players\":{},\"num_frames\":{},\
+                     \"player_names\":[",
+                    VERSION,
+                    w,
+                    h,
+                    self.player_names.len(),
+                    self.frames.len()));
+        for (ix, name) in self.player_names.iter().enumerate() {
+            let mut quoted = String::new();
+            json::write_str(&mut quoted, name).unwrap();
+            try!(write!(writer,
+                        "{}{}",
+                        if ix > 0 { "," } else { "" },
+                        quoted));
+        }
+        try!(write!(writer, "],\"productions\":"));
+        try!(write_grid(writer, w, h, |ix, out| {
+            write!(out, "{}", self.environment.production_map[ix])
+        }));
+        try!(write!(writer, ",\"frames\":["));
+        for (t, frame) in self.frames.iter().enumerate() {
+            if t > 0 {
+                try!(write!(writer, ","));
+            }
+            try!(write_grid(writer, w, h, |ix, out| {
+                let o = &frame.occupation_map[ix];
+                write!(out, "[{},{}]", o.tag, o.strength)
+            }));
+        }
+        try!(write!(writer, "],\"moves\":["));
+        for (t, dirs) in self.moves.iter().enumerate() {
+            if t > 0 {
+                try!(write!(writer, ","));
+            }
+            try!(write_grid(writer, w, h, |ix, out| {
+                write!(out, "{}", encode_dir(&dirs[ix]))
+            }));
+        }
+        try!(write!(writer, "]}}"));
+        Ok(())
+    }
+}
+
+fn write_grid<W, F>(writer: &mut W,
+                    width: usize,
+                    height: usize,
+                    mut write_cell: F)
+    -> io::Result<()>
+    where W: Write,
+          F: FnMut(usize, &mut W) -> io::Result<()>
+{
+    try!(write!(writer, "["));
+    for y in 0..height {
+        try!(write!(writer, "{}[", if y > 0 { "," } else { "" }));
+        for x in 0..width {
+            if x > 0 {
+                try!(write!(writer, ","));
+            }
+            try!(write_cell(y * width + x, writer));
+        }
+        try!(write!(writer, "]"));
+    }
+    write!(writer, "]")
+}
+
+#[cfg(test)]
+mod test {
+
+    use coord::Coord;
+    use dir::Dir;
+    use world::{Environment, Occupation, State};
+
+    use super::Replay;
+
+    #[test]
+    fn test_write_and_read_back()
+    {
+        let mut environment = Environment::create(0, 3, 2);
+        environment.production_map = vec![1, 2, 3, 4, 5, 6];
+        let mut state = State::for_environment(&environment);
+        state.occupation_map[0] = Occupation {
+            tag: 1,
+            strength: 10,
+        };
+        state.occupation_map[5] = Occupation {
+            tag: 2,
+            strength: 20,
+        };
+        let mut replay = Replay::new(environment,
+                                     vec!["foo".to_owned(),
+                                          "b\"ar".to_owned()],
+                                     state.clone());
+        let mut next = state.clone();
+        next.turn = 1;
+        replay.record_turn(&[vec![(Coord { x: 0, y: 0 }, Some(Dir::East))],
+                             vec![(Coord { x: 0, y: 0 }, Some(Dir::West))]],
+                           next);
+        let mut buffer = Vec::new();
+        replay.write(&mut buffer).unwrap();
+        let copy = Replay::read(&buffer[..]).unwrap();
+        assert_eq!(copy.player_names, replay.player_names);
+        assert_eq!(copy.environment.production_map,
+                   replay.environment.production_map);
+        assert_eq!(copy.frames.len(), 2);
+        assert_eq!(copy.frames[0].occupation_map[5].strength, 20);
+        assert_eq!(copy.moves, replay.moves);
+        // Moves of cells not owned by the player are dropped
+        assert_eq!(copy.actions_of(0, 1),
+                   vec![(Coord { x: 0, y: 0 }, Some(Dir::East))]);
+        assert_eq!(copy.actions_of(0, 2), vec![(Coord { x: 2, y: 1 }, None)]);
+    }
+
+    #[test]
+    fn test_player_stats()
+    {
+        let mut environment = Environment::create(0, 3, 1);
+        environment.production_map = vec![1, 2, 3];
+        let mut state = State::for_environment(&environment);
+        state.occupation_map[0] = Occupation {
+            tag: 1,
+            strength: 10,
+        };
+        state.occupation_map[2] = Occupation {
+            tag: 2,
+            strength: 5,
+        };
+        let mut replay = Replay::new(environment,
+                                     vec!["foo".to_owned(), "bar".to_owned()],
+                                     state.clone());
+        let mut next = state.clone();
+        next.turn = 1;
+        next.occupation_map[1] = Occupation {
+            tag: 1,
+            strength: 1,
+        };
+        next.occupation_map[2] = Occupation {
+            tag: 1,
+            strength: 2,
+        };
+        replay.record_turn(&[vec![], vec![]], next);
+        let stats = replay.player_stats();
+        assert_eq!(stats[0].territory, vec![1, 3]);
+        assert_eq!(stats[0].strength, vec![10, 13]);
+        assert_eq!(stats[0].production, vec![1, 6]);
+        assert_eq!(stats[0].eliminated, None);
+        assert_eq!(stats[1].territory, vec![1, 0]);
+        assert_eq!(stats[1].eliminated, Some(1));
+    }
+}
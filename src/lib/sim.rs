@@ -0,0 +1,500 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! In-process implementation of the Halite game rules.
+//!
+//! The rules follow the reference environment: still pieces gain their
+//! production, moving pieces of the same player merge (capped at 255), and
+//! then every piece damages the enemy pieces on the same and the adjacent
+//! cells while unowned cells only fight the pieces that move onto them.
+
+use std::cmp::{max, min};
+
+use rand::{Rng, SeedableRng, StdRng};
+
+use action::Action;
+use coord::Coord;
+use dir::Dir;
+use space::Space;
+use world::{Environment, Occupation, Production, State, Strength, Tag};
+
+pub const MAX_STRENGTH: i32 = 255;
+
+/// Resolves one turn of the game and returns the resulting state.
+///
+/// The moves of the player with the tag `t` are at `moves[t - 1]`.  Moves
+/// for cells the player doesn't own are ignored and cells without a move
+/// stay still.  If there are several moves for the same cell the last one
+/// takes effect.
+pub fn resolve_turn(space: &Space,
+                    productions: &[Production],
+                    state: &State,
+                    moves: &[Vec<Action>])
+    -> State
+{
+    let n = space.len();
+    let occupations = &state.occupation_map;
+    debug_assert_eq!(occupations.len(), n);
+    debug_assert_eq!(productions.len(), n);
+    let n_players = occupations.iter()
+                               .map(|o| o.tag as usize)
+                               .max()
+                               .unwrap_or(0);
+    // Targets of the pieces; unmoved pieces stay where they are
+    let mut targets = (0..n).collect::<Vec<usize>>();
+    for (p, player_moves) in moves.iter().enumerate() {
+        let tag = (p + 1) as Tag;
+        for &(ref coord, ref dir) in player_moves.iter() {
+            let ix = space.ix_of(coord);
+            if occupations[ix].tag == tag {
+                targets[ix] = match *dir {
+                    Some(ref dir) => space.ix_of(&coord.neighbor(dir)),
+                    None => ix,
+                };
+            }
+        }
+    }
+    // Gather the pieces of each player; the cells left behind by moving
+    // pieces are held by zero strength pieces unless somebody moves in.
+    let mut pieces = vec![vec![None; n]; n_players];
+    let mut neutrals = vec![0; n];
+    for ix in 0..n {
+        let o = &occupations[ix];
+        if o.tag == 0 {
+            neutrals[ix] = o.strength as i32;
+            continue;
+        }
+        let player_pieces = &mut pieces[o.tag as usize - 1];
+        let target = targets[ix];
+        let strength = if target == ix {
+            min(o.strength as i32 + productions[ix] as i32, MAX_STRENGTH)
+        } else {
+            o.strength as i32
+        };
+        player_pieces[target] = Some(match player_pieces[target] {
+            Some(s) => min(s + strength, MAX_STRENGTH),
+            None => strength,
+        });
+        if player_pieces[ix].is_none() {
+            player_pieces[ix] = Some(0);
+        }
+    }
+    // Every piece damages the enemy pieces on the same and the adjacent
+    // cells.  Unowned cells only fight the pieces on top of them.
+    let mut damages = vec![vec![None; n]; n_players];
+    let mut neutral_damages = vec![0; n];
+    for ix in 0..n {
+        let coord = space.coord_of(ix);
+        let mut reach = [ix; 5];
+        for (i, dir) in Dir::dirs().enumerate() {
+            reach[i + 1] = space.ix_of(&coord.neighbor(dir));
+        }
+        for c in 0..n_players {
+            if let Some(s) = pieces[c][ix] {
+                for d in 0..n_players {
+                    if d == c {
+                        continue;
+                    }
+                    for &jx in reach.iter() {
+                        if pieces[d][jx].is_some() {
+                            let damage = damages[d][jx].unwrap_or(0);
+                            damages[d][jx] = Some(damage + s);
+                        }
+                    }
+                }
+                if neutrals[ix] > 0 {
+                    let damage = damages[c][ix].unwrap_or(0);
+                    damages[c][ix] = Some(damage + neutrals[ix]);
+                    neutral_damages[ix] += s;
+                }
+            }
+        }
+    }
+    // Note that any damage, even zero, kills a piece of zero strength.
+    let mut occupation_map = Vec::with_capacity(n);
+    for ix in 0..n {
+        let mut occupation = Occupation {
+            tag: 0,
+            strength: max(neutrals[ix] - neutral_damages[ix], 0) as Strength,
+        };
+        for p in 0..n_players {
+            if let Some(s) = pieces[p][ix] {
+                let s = match damages[p][ix] {
+                    Some(d) if d >= s => continue,
+                    Some(d) => s - d,
+                    None => s,
+                };
+                occupation = Occupation {
+                    tag: (p + 1) as Tag,
+                    strength: s as Strength,
+                };
+            }
+        }
+        occupation_map.push(occupation);
+    }
+    State {
+        turn: state.turn + 1,
+        occupation_map: occupation_map,
+    }
+}
+
+/// Generates the map of a game for `n_players` players from `seed`.
+///
+/// The maps are not those of the reference environment.  The production
+/// and the strength of the cells are noise smoothed over the adjacent cells
+/// and the players start from single cells of full strength spread over
+/// the map.  The same seed always gives the same map.
+pub fn generate_map(width: i16, height: i16, n_players: usize, seed: usize)
+    -> (Space, Vec<Production>, State)
+{
+    debug_assert!(n_players <= width as usize);
+    let space = Space::with_dims(width, height);
+    let seed: &[_] = &[seed];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let mut noise = |high: i32| {
+        (0..space.len()).map(|_| rng.gen_range(0, high)).collect::<Vec<_>>()
+    };
+    let smooth = |cells: Vec<i32>| {
+        (0..space.len())
+            .map(|ix| {
+                Dir::dirs()
+                    .map(|dir| cells[space.adjacent_ix(ix, dir)])
+                    .fold(cells[ix], |sum, c| sum + c) / 5
+            })
+            .collect::<Vec<_>>()
+    };
+    let production_map = smooth(noise(16))
+                             .into_iter()
+                             .map(|p| p as Production)
+                             .collect();
+    let mut occupation_map = smooth(noise(MAX_STRENGTH + 1))
+                                 .into_iter()
+                                 .map(|s| {
+                                     Occupation {
+                                         tag: 0,
+                                         strength: s as Strength,
+                                     }
+                                 })
+                                 .collect::<Vec<_>>();
+    for p in 0..n_players {
+        let coord = Coord {
+            x: ((2 * p + 1) * width as usize / (2 * n_players)) as i16,
+            y: if p % 2 == 0 { height / 4 } else { 3 * height / 4 },
+        };
+        occupation_map[space.ix_of(&coord)] = Occupation {
+            tag: (p + 1) as Tag,
+            strength: MAX_STRENGTH as Strength,
+        };
+    }
+    let state = State {
+        turn: 0,
+        occupation_map: occupation_map,
+    };
+    (space, production_map, state)
+}
+
+/// A game in progress.
+pub struct Game
+{
+    space: Space,
+    production_map: Vec<Production>,
+    total_turns: u32,
+    state: State,
+    // The last turn on which each player still held some territory
+    last_turn_alive: Vec<u32>,
+}
+
+impl Game
+{
+    /// Starts a game from the initial state.  The number of players is the
+    /// largest tag found on the map.
+    pub fn new(space: Space,
+               production_map: Vec<Production>,
+               init_state: State)
+        -> Self
+    {
+        debug_assert_eq!(production_map.len(), space.len());
+        debug_assert_eq!(init_state.occupation_map.len(), space.len());
+        let n_players = init_state.occupation_map
+                                  .iter()
+                                  .map(|o| o.tag as usize)
+                                  .max()
+                                  .unwrap_or(0);
+        let total_turns = Environment::create(0,
+                                              space.width(),
+                                              space.height())
+                              .total_turns;
+        Game {
+            space: space,
+            production_map: production_map,
+            total_turns: total_turns,
+            last_turn_alive: vec![init_state.turn; n_players],
+            state: init_state,
+        }
+    }
+
+    pub fn space(&self) -> &Space
+    {
+        &self.space
+    }
+
+    pub fn production_map(&self) -> &[Production]
+    {
+        &self.production_map
+    }
+
+    pub fn state(&self) -> &State
+    {
+        &self.state
+    }
+
+    pub fn total_turns(&self) -> u32
+    {
+        self.total_turns
+    }
+
+    pub fn n_players(&self) -> usize
+    {
+        self.last_turn_alive.len()
+    }
+
+    /// Returns the environment as seen by the player `tag`.
+    pub fn environment_for(&self, tag: Tag) -> Environment
+    {
+        let mut environment = Environment::create(tag,
+                                                  self.space.width(),
+                                                  self.space.height());
+        environment.production_map = self.production_map.clone();
+        environment
+    }
+
+    pub fn territory_of(&self, tag: Tag) -> usize
+    {
+        self.state
+            .occupation_map
+            .iter()
+            .filter(|o| o.tag == tag)
+            .count()
+    }
+
+    pub fn is_alive(&self, tag: Tag) -> bool
+    {
+        tag > 0 && tag as usize <= self.n_players() &&
+        self.last_turn_alive[tag as usize - 1] == self.state.turn
+    }
+
+    pub fn is_over(&self) -> bool
+    {
+        let n_alive = (1..self.n_players() + 1)
+                          .filter(|&t| self.is_alive(t as Tag))
+                          .count();
+        n_alive <= 1 || self.state.turn >= self.total_turns
+    }
+
+    /// Drops the player `tag` from the game the way the environment drops
+    /// the players that crash or time out: its pieces become unowned.
+    pub fn drop_player(&mut self, tag: Tag)
+    {
+        for o in self.state.occupation_map.iter_mut() {
+            if o.tag == tag {
+                o.tag = 0;
+            }
+        }
+    }
+
+    /// Plays one turn; see `resolve_turn` for how `moves` are laid out.
+    pub fn step(&mut self, moves: &[Vec<Action>])
+    {
+        let next = resolve_turn(&self.space,
+                                &self.production_map,
+                                &self.state,
+                                moves);
+        for o in next.occupation_map.iter() {
+            if o.tag != 0 {
+                self.last_turn_alive[o.tag as usize - 1] = next.turn;
+            }
+        }
+        self.state = next;
+    }
+
+    /// Returns the rank (starting from 1) of each player in tag order.
+    ///
+    /// Players that survive longer rank higher.  Players that are alive at
+    /// the same time are ranked by their territory and then by their total
+    /// strength.
+    pub fn rankings(&self) -> Vec<usize>
+    {
+        let n_players = self.n_players();
+        let mut territory = vec![0; n_players];
+        let mut strength = vec![0; n_players];
+        for o in self.state.occupation_map.iter() {
+            if o.tag != 0 {
+                territory[o.tag as usize - 1] += 1;
+                strength[o.tag as usize - 1] += o.strength as i32;
+            }
+        }
+        let mut order = (0..n_players).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| {
+            (self.last_turn_alive[b], territory[b], strength[b])
+                .cmp(&(self.last_turn_alive[a], territory[a], strength[a]))
+        });
+        let mut rankings = vec![0; n_players];
+        for (rank, &p) in order.iter().enumerate() {
+            rankings[p] = rank + 1;
+        }
+        rankings
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use coord::Coord;
+    use dir::Dir;
+    use space::Space;
+    use world::{Occupation, State};
+
+    use super::*;
+
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn state_of(cells: &[(u8, i16)]) -> State
+    {
+        State {
+            turn: 0,
+            occupation_map: cells.iter()
+                .map(|&(tag, strength)| Occupation { tag: tag, strength: strength })
+                .collect(),
+        }
+    }
+
+    fn cells_of(state: &State) -> Vec<(u8, i16)>
+    {
+        state.occupation_map
+             .iter()
+             .map(|o| (o.tag, o.strength))
+             .collect()
+    }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_production_and_merging()
+    {
+        let space = Space::with_dims(4, 1);
+        let productions = vec![3, 5, 7, 0];
+        let state = state_of(&[(1, 200), (1, 100), (1, 10), (0, 0)]);
+        let moves = vec![vec![(Coord { x: 0, y: 0 }, Some(Dir::East)),
+                              (Coord { x: 2, y: 0 }, None)]];
+        let next = resolve_turn(&space, &productions, &state, &moves);
+        assert_eq!(cells_of(&next), vec![(1, 0), (1, 255), (1, 17), (0, 0)]);
+        assert_eq!(next.turn, 1);
+    }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_neutral_capture_and_wrapping()
+    {
+        let space = Space::with_dims(3, 1);
+        let productions = vec![0, 0, 0];
+        let state = state_of(&[(1, 50), (0, 20), (0, 60)]);
+        // Moving west from the first cell wraps to the last one
+        let moves = vec![vec![(Coord { x: 0, y: 0 }, Some(Dir::West))]];
+        let next = resolve_turn(&space, &productions, &state, &moves);
+        assert_eq!(cells_of(&next), vec![(1, 0), (0, 20), (0, 10)]);
+        let moves = vec![vec![(Coord { x: 0, y: 0 }, Some(Dir::East))]];
+        let next = resolve_turn(&space, &productions, &state, &moves);
+        assert_eq!(cells_of(&next), vec![(1, 0), (1, 30), (0, 60)]);
+    }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_combat_damages_adjacent_cells()
+    {
+        let space = Space::with_dims(5, 1);
+        let productions = vec![0; 5];
+        let state = state_of(&[(1, 0), (1, 30), (0, 0), (2, 100), (2, 10)]);
+        // Player 2 charges into the gap and overpowers the piece next to it
+        // while the rear piece of player 2 kills the empty cell of player 1
+        // across the edge of the map.
+        let moves = vec![vec![],
+                         vec![(Coord { x: 3, y: 0 }, Some(Dir::West))]];
+        let next = resolve_turn(&space, &productions, &state, &moves);
+        assert_eq!(cells_of(&next), vec![(0, 0), (0, 0), (2, 70), (2, 0), (2, 10)]);
+    }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_ignores_moves_of_foreign_cells()
+    {
+        let space = Space::with_dims(5, 1);
+        let productions = vec![1; 5];
+        let state = state_of(&[(1, 10), (0, 0), (2, 10), (0, 0), (0, 0)]);
+        let moves = vec![vec![(Coord { x: 2, y: 0 }, Some(Dir::West))]];
+        let next = resolve_turn(&space, &productions, &state, &moves);
+        assert_eq!(cells_of(&next), vec![(1, 11), (0, 0), (2, 11), (0, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn test_game_ends_on_elimination()
+    {
+        let space = Space::with_dims(3, 1);
+        let state = state_of(&[(1, 100), (0, 0), (2, 5)]);
+        let mut game = Game::new(space, vec![0, 0, 0], state);
+        assert_eq!(game.n_players(), 2);
+        assert!(!game.is_over());
+        game.step(&[vec![(Coord { x: 0, y: 0 }, Some(Dir::East))], vec![]]);
+        assert!(game.is_alive(1));
+        assert!(!game.is_alive(2));
+        assert!(game.is_over());
+        assert_eq!(game.rankings(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_dropped_player_ranks_last()
+    {
+        let space = Space::with_dims(6, 1);
+        let state = state_of(&[(1, 10), (0, 0), (2, 10), (0, 0), (3, 10),
+                               (0, 0)]);
+        let mut game = Game::new(space, vec![0; 6], state);
+        game.drop_player(2);
+        game.step(&[vec![], vec![], vec![]]);
+        assert!(!game.is_alive(2));
+        assert_eq!(cells_of(game.state())[2], (0, 10));
+        assert_eq!(game.rankings()[1], 3);
+    }
+
+    #[test]
+    fn test_generate_map()
+    {
+        let (space, productions, state) = generate_map(30, 20, 3, 7);
+        assert_eq!(space.len(), 600);
+        assert_eq!(productions.len(), 600);
+        assert!(productions.iter().all(|&p| 0 <= p && p < 16));
+        let mut tags = state.occupation_map
+                            .iter()
+                            .map(|o| o.tag)
+                            .filter(|&t| t != 0)
+                            .collect::<Vec<_>>();
+        tags.sort();
+        assert_eq!(tags, vec![1, 2, 3]);
+        let (_, again, _) = generate_map(30, 20, 3, 7);
+        assert_eq!(productions, again);
+        let (_, other, _) = generate_map(30, 20, 3, 8);
+        assert!(productions != other);
+        let game = Game::new(space, productions, state);
+        assert_eq!(game.n_players(), 3);
+    }
+}
@@ -79,6 +79,18 @@ impl Space
         }
     }
 
+    /// Returns the index of the cell at `coord`.  Coordinates outside the
+    /// space are wrapped around the edges.
+    #[inline]
+    pub fn ix_of(&self, coord: &Coord) -> usize
+    {
+        let w = self.w as i32;
+        let h = self.h as i32;
+        let x = ((coord.x as i32 % w) + w) % w;
+        let y = ((coord.y as i32 % h) + h) % h;
+        (y * w + x) as usize
+    }
+
     #[inline]
     pub fn adjacent_ix(&self, ix: usize, dir: &Dir) -> usize
     {
@@ -244,12 +244,12 @@ impl<'a> Flux<'a>
     }
 }
 
-struct Sources<'a>
+struct Sources
 {
 
 }
 
-struct Sinks<'a>
+struct Sinks
 {
 }
 
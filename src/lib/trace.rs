@@ -0,0 +1,210 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Protocol traces recorded with `io::Connection::trace_to`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use coord::Coord;
+use dir::Dir;
+use io::{Error, decode_actions};
+
+#[derive(Clone, Debug)]
+pub struct Trace
+{
+    /// The lines received from the environment.
+    pub received: Vec<String>,
+    /// The lines sent to the environment.  The first one is the bot name
+    /// and the rest are the actions of each turn.
+    pub sent: Vec<String>,
+}
+
+impl Trace
+{
+    pub fn load<P>(path: P) -> Result<Self, Error>
+        where P: AsRef<Path>
+    {
+        let file = try!(File::open(path));
+        Trace::read(BufReader::new(file))
+    }
+
+    pub fn read<R>(reader: R) -> Result<Self, Error>
+        where R: BufRead
+    {
+        let mut trace = Trace {
+            received: Vec::new(),
+            sent: Vec::new(),
+        };
+        for line in reader.lines() {
+            let line = try!(line);
+            if line.starts_with("< ") {
+                trace.received.push(line[2..].to_owned());
+            } else if line.starts_with("> ") {
+                trace.sent.push(line[2..].to_owned());
+            } else {
+                return Err(Error::ParseError(format!("bad trace line: {:?}",
+                                                     line)));
+            }
+        }
+        Ok(trace)
+    }
+
+    /// Returns the received lines as they came from the environment.
+    pub fn input(&self) -> String
+    {
+        let mut input = String::new();
+        for line in self.received.iter() {
+            input.push_str(line);
+            input.push('\n');
+        }
+        input
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference
+{
+    Name
+    {
+        recorded: String,
+        replayed: String,
+    },
+    Move
+    {
+        turn: usize,
+        coord: Coord,
+        recorded: Option<Dir>,
+        replayed: Option<Dir>,
+    },
+    Length
+    {
+        recorded: usize,
+        replayed: usize,
+    },
+}
+
+impl fmt::Display for Difference
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self {
+            Difference::Name { ref recorded, ref replayed } => {
+                write!(f, "name: recorded {}, replayed {}", recorded, replayed)
+            }
+            Difference::Move { turn, ref coord, recorded, replayed } => {
+                write!(f,
+                       "turn {}, cell ({}, {}): recorded {:?}, replayed {:?}",
+                       turn,
+                       coord.x,
+                       coord.y,
+                       recorded,
+                       replayed)
+            }
+            Difference::Length { recorded, replayed } => {
+                write!(f,
+                       "turns: recorded {}, replayed {}",
+                       recorded,
+                       replayed)
+            }
+        }
+    }
+}
+
+fn moves_of(line: &str) -> Result<BTreeMap<Coord, Option<Dir>>, Error>
+{
+    let mut moves = BTreeMap::new();
+    for (coord, dir) in try!(decode_actions(line)) {
+        moves.insert(coord, dir);
+    }
+    Ok(moves)
+}
+
+/// Compares two sequences of sent lines.  Cells without an action are
+/// considered to stay still so only the actual differences in moves count.
+pub fn compare(recorded: &[String],
+               replayed: &[String])
+    -> Result<Vec<Difference>, Error>
+{
+    let mut differences = Vec::new();
+    if recorded.first() != replayed.first() {
+        differences.push(Difference::Name {
+            recorded: recorded.first().cloned().unwrap_or(String::new()),
+            replayed: replayed.first().cloned().unwrap_or(String::new()),
+        });
+    }
+    for (turn, (a, b)) in recorded.iter()
+                                  .zip(replayed.iter())
+                                  .enumerate()
+                                  .skip(1) {
+        let a = try!(moves_of(a));
+        let b = try!(moves_of(b));
+        let coords = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+        for coord in coords {
+            let recorded = a.get(coord).cloned().unwrap_or(None);
+            let replayed = b.get(coord).cloned().unwrap_or(None);
+            if recorded != replayed {
+                differences.push(Difference::Move {
+                    turn: turn,
+                    coord: coord.clone(),
+                    recorded: recorded,
+                    replayed: replayed,
+                });
+            }
+        }
+    }
+    if recorded.len() != replayed.len() {
+        differences.push(Difference::Length {
+            recorded: recorded.len().saturating_sub(1),
+            replayed: replayed.len().saturating_sub(1),
+        });
+    }
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod test {
+
+    use coord::Coord;
+    use dir::Dir;
+
+    use super::{Difference, Trace, compare};
+
+    #[test]
+    fn test_read_and_compare()
+    {
+        let text = "< 1\n< 2 1\n< 1 1\n< 1 1 1 0 5 0\n> UA_Foo_1\n\
+                    < 1 1 1 0 5 0\n> 0 0 2 1 0 0\n";
+        let trace = Trace::read(text.as_bytes()).unwrap();
+        assert_eq!(trace.received.len(), 5);
+        assert_eq!(trace.input(), "1\n2 1\n1 1\n1 1 1 0 5 0\n1 1 1 0 5 0\n");
+        let same = vec!["UA_Foo_1".to_owned(), "0 0 2".to_owned()];
+        assert_eq!(compare(&trace.sent, &same).unwrap(), vec![]);
+        let other = vec!["UA_Foo_1".to_owned(), "0 0 0".to_owned()];
+        assert_eq!(compare(&trace.sent, &other).unwrap(),
+                   vec![Difference::Move {
+                            turn: 1,
+                            coord: Coord { x: 0, y: 0 },
+                            recorded: Some(Dir::East),
+                            replayed: None,
+                        }]);
+        assert!(Trace::read("? junk\n".as_bytes()).is_err());
+    }
+}
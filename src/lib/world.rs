@@ -23,7 +23,7 @@ pub type Production = i16;
 
 pub type Strength = i16;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Environment
 {
     pub my_tag: Tag,
@@ -32,9 +32,12 @@ pub struct Environment
     pub production_map: Vec<Production>,
 }
 
+// The game lasts for `sqrt(width * height) * 10` turns truncated; this is
+// the `maxTurnNumber` of the Halite environment (computed in doubles there
+// too) and the simulator needs it to end the game on the same turn.
 fn total_turns(width: i16, height: i16) -> u32
 {
-    ((width * height) as f32).sqrt().floor() as u32
+    ((width as f64 * height as f64).sqrt() * 10.0) as u32
 }
 
 impl Environment
@@ -59,7 +62,7 @@ pub struct Occupation
     pub strength: Strength,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct State
 {
     pub turn: u32,
@@ -77,3 +80,19 @@ impl State
         }
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use super::total_turns;
+
+    #[test]
+    fn test_total_turns_match_halite()
+    {
+        assert_eq!(total_turns(20, 20), 200);
+        assert_eq!(total_turns(30, 30), 300);
+        assert_eq!(total_turns(50, 50), 500);
+        assert_eq!(total_turns(20, 30), 244);
+        assert_eq!(total_turns(25, 40), 316);
+    }
+}
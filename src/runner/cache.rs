@@ -0,0 +1,269 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A cache of the outcomes of the matches played.
+//!
+//! The cache assumes that the same bots play the same game every time on a
+//! map of the same size and seed.  That holds only for bots that use no
+//! randomness and never run out of time; for other bots the cached outcome
+//! is just one of the possible ones.  The outcome is cached under the
+//! engine, the digests of the bots in seat order, the dimensions of the map
+//! and the seed.  Each outcome is a small JSON file in the cache directory
+//! named after the digest of its key.  Matches without a seed are never
+//! cached and neither are those in which a player failed: a timeout may be
+//! down to the load of the machine and the environment does not tell it
+//! apart from a crash.
+//!
+//! The statistics of the players are not cached but read again from the
+//! replay, so an outcome whose replay is gone counts as a miss.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use md5;
+use data_encoding::hex;
+
+use ua::json::{self, Value};
+use ua::replay::Replay;
+
+use checkpoint::{indices, indices_of, int_of, object, path, seed, seed_of,
+                 str_of};
+use pool::Job;
+use runner::{self, Env, Error, Outcome};
+
+const VERSION: i64 = 4;
+
+pub const CACHE_DIR: &'static str = "cache";
+
+pub struct Cache
+{
+    dir: PathBuf,
+}
+
+fn read_json(path: &Path) -> Option<Value>
+{
+    let mut input = String::new();
+    if File::open(path)
+           .and_then(|mut f| f.read_to_string(&mut input))
+           .is_err() {
+        return None;
+    }
+    json::parse(&input).ok()
+}
+
+/// The replay the cache entry in the file `path` refers to if the entry can
+/// be read.
+pub fn replay_of(path: &Path) -> Option<PathBuf>
+{
+    read_json(path).and_then(|root| {
+        str_of(&root, "replay").ok().map(PathBuf::from)
+    })
+}
+
+fn outcome_from_json(root: &Value, n_players: usize)
+    -> Result<Outcome, Error>
+{
+    let outcome = Outcome {
+        seed: try!(seed_of(root, "seed")),
+        rankings: try!(indices_of(root, "rankings")),
+        hlt_path: PathBuf::from(try!(str_of(root, "replay"))),
+        stats: Vec::new(),
+        failures: Vec::new(),
+    };
+    if outcome.rankings.len() != n_players {
+        return Err(Error::Format("outcome does not match the match"
+                                     .to_owned()));
+    }
+    Ok(outcome)
+}
+
+impl Cache
+{
+    /// Opens the cache in `dir` creating the directory if needed.
+    pub fn open<P>(dir: P) -> Result<Self, Error>
+        where P: AsRef<Path>
+    {
+        try!(fs::create_dir_all(dir.as_ref()));
+        Ok(Cache { dir: dir.as_ref().to_path_buf() })
+    }
+
+    /// Opens the cache in the working directory of `env`.
+    pub fn in_work_dir(env: &Env) -> Result<Self, Error>
+    {
+        Cache::open(env.work_dir().join(CACHE_DIR))
+    }
+
+    /// The key of `job` or `None` if the job has no seed.
+    fn key(job: &Job) -> Result<Option<String>, Error>
+    {
+        let seed = match job.seed {
+            Some(seed) => seed,
+            None => return Ok(None),
+        };
+        let mut key = format!("{} {}x{} {}",
+                              job.engine.name(),
+                              job.width,
+                              job.height,
+                              seed);
+        for bot in job.bots.iter() {
+            key.push(' ');
+            key.push_str(&try!(runner::bot_digest(bot)));
+        }
+        Ok(Some(key))
+    }
+
+    fn path_of(&self, key: &str) -> PathBuf
+    {
+        let digest = hex::encode(&md5::compute(key.as_bytes()));
+        self.dir.join(format!("{}.json", digest))
+    }
+
+    /// The cached outcome of `job` if there is one.  An entry that cannot
+    /// be read or whose replay cannot be read counts as a miss.
+    pub fn get(&self, job: &Job) -> Option<Outcome>
+    {
+        let key = match Cache::key(job) {
+            Ok(Some(key)) => key,
+            _ => return None,
+        };
+        let root = match read_json(&self.path_of(&key)) {
+            Some(root) => root,
+            None => return None,
+        };
+        if int_of(&root, "version").ok() != Some(VERSION) ||
+           str_of(&root, "key").ok() != Some(&key) {
+            return None;
+        }
+        let mut outcome = match outcome_from_json(&root, job.bots.len()) {
+            Ok(outcome) => outcome,
+            Err(_) => return None,
+        };
+        match Replay::load(&outcome.hlt_path) {
+            Ok(ref replay) if replay.n_players() == job.bots.len() => {
+                outcome.stats = replay.player_stats();
+                Some(outcome)
+            }
+            _ => None,
+        }
+    }
+
+    /// Stores the outcome of `job` unless it has no seed or a player
+    /// failed.
+    pub fn put(&self, job: &Job, outcome: &Outcome) -> Result<(), Error>
+    {
+        if !outcome.failures.is_empty() {
+            return Ok(());
+        }
+        let key = match try!(Cache::key(job)) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let root = object(vec![("version", Value::Number(VERSION as f64)),
+                               ("key", Value::String(key.clone())),
+                               ("seed", seed(outcome.seed)),
+                               ("rankings", indices(&outcome.rankings)),
+                               ("replay", path(&outcome.hlt_path))]);
+        let path = self.path_of(&key);
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(write!(file, "{}\n", root));
+        }
+        try!(fs::rename(&tmp_path, path));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+    use std::sync::Arc;
+
+    use ua::replay::Replay;
+    use ua::sim::{self, Game};
+
+    use checkpoint::fixture::{self, TempDir};
+    use inproc::InProcess;
+    use pool::Job;
+    use runner::{External, Failure, Outcome};
+    use super::Cache;
+
+    #[test]
+    fn test_put_and_get()
+    {
+        let dir = TempDir::new("cache");
+        let cache = Cache::open(&dir.path().join("cache")).unwrap();
+        let hlt_path = dir.path().join("42.hlt");
+        let (space, productions, state) = sim::generate_map(10, 10, 2, 42);
+        let game = Game::new(space, productions, state.clone());
+        Replay::new(game.environment_for(0),
+                    vec!["a".to_owned(), "b".to_owned()],
+                    state)
+            .save(&hlt_path)
+            .unwrap();
+        let mut bot = fixture::bot();
+        let other = bot.clone();
+        bot.set_param("weight", 1.0);
+        let mut job = Job {
+            width: 10,
+            height: 10,
+            seed: Some(42),
+            bots: vec![bot, other],
+            engine: Arc::new(External),
+        };
+        let mut outcome = Outcome {
+            // A seed a double cannot hold
+            seed: (1 << 53) + 1,
+            rankings: vec![2, 1],
+            hlt_path: hlt_path.clone(),
+            stats: Vec::new(),
+            failures: vec![Failure {
+                               player: 0,
+                               turn: Some(17),
+                               log_path: None,
+                               log: Some("timed out".to_owned()),
+                           }],
+        };
+        // A failure may have been down to the load of the machine
+        cache.put(&job, &outcome).unwrap();
+        assert!(cache.get(&job).is_none());
+        outcome.failures.clear();
+        cache.put(&job, &outcome).unwrap();
+        let cached = cache.get(&job).unwrap();
+        assert_eq!(cached.seed, outcome.seed);
+        assert_eq!(cached.rankings, outcome.rankings);
+        assert_eq!(cached.hlt_path, outcome.hlt_path);
+        assert_eq!(cached.stats.len(), 2);
+        assert!(cached.failures.is_empty());
+        // Swapping the seats is another match
+        job.bots.reverse();
+        assert!(cache.get(&job).is_none());
+        job.bots.reverse();
+        job.engine = Arc::new(InProcess::new());
+        assert!(cache.get(&job).is_none());
+        job.engine = Arc::new(External);
+        job.seed = None;
+        assert!(cache.get(&job).is_none());
+        job.seed = Some(42);
+        // Without the replay there are no statistics
+        fs::remove_file(&hlt_path).unwrap();
+        assert!(cache.get(&job).is_none());
+    }
+}
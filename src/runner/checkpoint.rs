@@ -0,0 +1,648 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The state of a tournament saved between the games.
+//!
+//! The checkpoint is a JSON file holding the population, that is each bot
+//! with its parameters and ratings, and the games played so far.  It is
+//! rewritten after every round of games so that an interrupted tournament
+//! can be resumed from where it left off.  When the population is evolved the
+//! games are those of the current generation.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use ua::json::{self, Value};
+
+use rating::{RatingSystem, Skill};
+use runner::{Bot, Error};
+
+const VERSION: i64 = 9;
+
+/// A game played in the tournament.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Game
+{
+    pub width: i16,
+    pub height: i16,
+    pub seed: u64,
+    /// The indices of the players in the population
+    pub players: Vec<usize>,
+    /// The rank of each player in the order of `players`
+    pub rankings: Vec<usize>,
+    /// The indices of the players that crashed or timed out
+    pub failed: Vec<usize>,
+    pub hlt_path: PathBuf,
+}
+
+/// A bot in the population.
+#[derive(Clone, Debug)]
+pub struct Entry
+{
+    /// The name of the bot in the spec file it came from, if any
+    pub name: Option<String>,
+    pub bot: Bot,
+    /// The skill of the bot by the name of each rating system
+    pub ratings: BTreeMap<String, Skill>,
+    /// The ratings the bot had when the current generation began
+    pub initial_ratings: BTreeMap<String, Skill>,
+    /// The number of games the bot has crashed or timed out in
+    pub failures: usize,
+}
+
+impl Entry
+{
+    pub fn new(bot: Bot) -> Self
+    {
+        Entry {
+            name: None,
+            bot: bot,
+            ratings: BTreeMap::new(),
+            initial_ratings: BTreeMap::new(),
+            failures: 0,
+        }
+    }
+
+    /// The skill of the bot by `system`; the initial skill if the bot has
+    /// not been rated by it yet.
+    pub fn skill(&self, system: &RatingSystem) -> Skill
+    {
+        match self.ratings.get(system.name()) {
+            Some(skill) => skill.clone(),
+            None => system.initial(),
+        }
+    }
+}
+
+/// The options that make up a tournament.  A tournament resumed with other
+/// options would no longer be the one that was begun.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings
+{
+    pub n_players: (usize, usize),
+    pub map_sizes: Vec<i16>,
+    pub max_failures: usize,
+    /// The rating systems as `rating::parse` takes them
+    pub rating_systems: Vec<String>,
+    pub matchmaking: String,
+    pub engine: String,
+}
+
+impl Settings
+{
+    fn fields(&self) -> Vec<(&'static str, String)>
+    {
+        let sizes = self.map_sizes
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<_>>();
+        vec![("players",
+              format!("{}-{}", self.n_players.0, self.n_players.1)),
+             ("map sizes", sizes.join(",")),
+             ("max failures", self.max_failures.to_string()),
+             ("ratings", self.rating_systems.join(",")),
+             ("matchmaking", self.matchmaking.clone()),
+             ("engine", self.engine.clone())]
+    }
+
+    /// Describes how `other` differs from these settings, if it does.
+    pub fn mismatch(&self, other: &Settings) -> Option<String>
+    {
+        self.fields()
+            .into_iter()
+            .zip(other.fields().into_iter())
+            .find(|&((_, ref mine), (_, ref theirs))| mine != theirs)
+            .map(|((name, mine), (_, theirs))| {
+                format!("{} {} instead of {}", name, theirs, mine)
+            })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint
+{
+    pub bots: Vec<Entry>,
+    pub games: Vec<Game>,
+    /// The master seed of the tournament
+    pub seed: usize,
+    /// The number of generations bred from the initial population
+    pub generation: usize,
+    /// The number of games in a round, fixed when the tournament begins
+    pub round_size: usize,
+    pub settings: Settings,
+}
+
+fn err(message: &str) -> Error
+{
+    Error::Format(message.to_owned())
+}
+
+pub fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, Error>
+{
+    value.get(key).ok_or_else(|| err(&format!("missing field '{}'", key)))
+}
+
+fn number_of(value: &Value, key: &str) -> Result<f64, Error>
+{
+    try!(field(value, key))
+        .as_f64()
+        .ok_or_else(|| err(&format!("field '{}' is not a number", key)))
+}
+
+pub fn int_of(value: &Value, key: &str) -> Result<i64, Error>
+{
+    try!(field(value, key))
+        .as_i64()
+        .ok_or_else(|| err(&format!("field '{}' is not an integer", key)))
+}
+
+pub fn str_of<'a>(value: &'a Value, key: &str) -> Result<&'a str, Error>
+{
+    try!(field(value, key))
+        .as_str()
+        .ok_or_else(|| err(&format!("field '{}' is not a string", key)))
+}
+
+pub fn array_of<'a>(value: &'a Value, key: &str)
+    -> Result<&'a Vec<Value>, Error>
+{
+    try!(field(value, key))
+        .as_array()
+        .ok_or_else(|| err(&format!("field '{}' is not an array", key)))
+}
+
+pub fn indices_of(value: &Value, key: &str) -> Result<Vec<usize>, Error>
+{
+    try!(array_of(value, key))
+        .iter()
+        .map(|v| {
+            match v.as_i64() {
+                Some(ix) if ix >= 0 => Ok(ix as usize),
+                _ => Err(err(&format!("bad index in '{}'", key))),
+            }
+        })
+        .collect()
+}
+
+/// Reads a seed written by `seed`.
+pub fn seed_of(value: &Value, key: &str) -> Result<u64, Error>
+{
+    try!(str_of(value, key))
+        .parse()
+        .map_err(|_| err(&format!("field '{}' is not a seed", key)))
+}
+
+pub fn object(fields: Vec<(&str, Value)>) -> Value
+{
+    Value::Object(fields.into_iter()
+                        .map(|(k, v)| (k.to_owned(), v))
+                        .collect())
+}
+
+pub fn indices(ixs: &[usize]) -> Value
+{
+    Value::Array(ixs.iter().map(|&ix| Value::Number(ix as f64)).collect())
+}
+
+/// A seed as a string since a JSON number, that is a double, would round
+/// the seeds beyond 2^53.
+pub fn seed(seed: u64) -> Value
+{
+    Value::String(seed.to_string())
+}
+
+pub fn path(p: &Path) -> Value
+{
+    Value::String(p.to_string_lossy().into_owned())
+}
+
+fn skill_to_json(skill: &Skill) -> Value
+{
+    object(vec![("mu", Value::Number(skill.mu)),
+                ("sigma", Value::Number(skill.sigma)),
+                ("volatility", Value::Number(skill.volatility))])
+}
+
+fn skill_from_json(value: &Value) -> Result<Skill, Error>
+{
+    Ok(Skill {
+        mu: try!(number_of(value, "mu")),
+        sigma: try!(number_of(value, "sigma")),
+        volatility: try!(number_of(value, "volatility")),
+    })
+}
+
+fn ratings_to_json(ratings: &BTreeMap<String, Skill>) -> Value
+{
+    Value::Object(ratings.iter()
+                         .map(|(name, s)| (name.clone(), skill_to_json(s)))
+                         .collect())
+}
+
+fn ratings_from_json(value: &Value, key: &str)
+    -> Result<BTreeMap<String, Skill>, Error>
+{
+    match try!(field(value, key)) {
+        &Value::Object(ref skills) => {
+            let mut ratings = BTreeMap::new();
+            for (name, skill) in skills.iter() {
+                ratings.insert(name.clone(), try!(skill_from_json(skill)));
+            }
+            Ok(ratings)
+        }
+        _ => Err(err(&format!("field '{}' is not an object", key))),
+    }
+}
+
+fn entry_to_json(entry: &Entry) -> Value
+{
+    let bot = &entry.bot;
+    let params = bot.params()
+                    .iter()
+                    .map(|(k, &v)| (k.clone(), Value::Number(v as f64)))
+                    .collect::<BTreeMap<_, _>>();
+    object(vec![("name",
+                 entry.name
+                      .as_ref()
+                      .map(|n| Value::String(n.clone()))
+                      .unwrap_or(Value::Null)),
+                ("exe", path(bot.exe_path())),
+                ("brain",
+                 bot.brain_name()
+                    .map(|b| Value::String(b.to_owned()))
+                    .unwrap_or(Value::Null)),
+                ("params", Value::Object(params)),
+                ("ratings", ratings_to_json(&entry.ratings)),
+                ("initial_ratings", ratings_to_json(&entry.initial_ratings)),
+                ("failures", Value::Number(entry.failures as f64))])
+}
+
+fn entry_from_json(value: &Value) -> Result<Entry, Error>
+{
+    let mut bot = try!(Bot::new(try!(str_of(value, "exe"))));
+    match try!(field(value, "brain")) {
+        &Value::Null => {}
+        &Value::String(ref brain) => {
+            bot.brain(brain);
+        }
+        _ => return Err(err("field 'brain' is not a string")),
+    }
+    match try!(field(value, "params")) {
+        &Value::Object(ref params) => {
+            for (k, v) in params.iter() {
+                let v = try!(v.as_f64().ok_or_else(|| {
+                    err(&format!("parameter '{}' is not a number", k))
+                }));
+                bot.set_param(k, v as f32);
+            }
+        }
+        _ => return Err(err("field 'params' is not an object")),
+    }
+    let name = match try!(field(value, "name")) {
+        &Value::Null => None,
+        &Value::String(ref name) => Some(name.clone()),
+        _ => return Err(err("field 'name' is not a string")),
+    };
+    Ok(Entry {
+        name: name,
+        bot: bot,
+        ratings: try!(ratings_from_json(value, "ratings")),
+        initial_ratings: try!(ratings_from_json(value, "initial_ratings")),
+        failures: try!(int_of(value, "failures")) as usize,
+    })
+}
+
+fn game_to_json(game: &Game) -> Value
+{
+    object(vec![("width", Value::Number(game.width as f64)),
+                ("height", Value::Number(game.height as f64)),
+                ("seed", seed(game.seed)),
+                ("players", indices(&game.players)),
+                ("rankings", indices(&game.rankings)),
+                ("failed", indices(&game.failed)),
+                ("replay", path(&game.hlt_path))])
+}
+
+fn game_from_json(value: &Value, n_bots: usize) -> Result<Game, Error>
+{
+    let game = Game {
+        width: try!(int_of(value, "width")) as i16,
+        height: try!(int_of(value, "height")) as i16,
+        seed: try!(seed_of(value, "seed")),
+        players: try!(indices_of(value, "players")),
+        rankings: try!(indices_of(value, "rankings")),
+        failed: try!(indices_of(value, "failed")),
+        hlt_path: PathBuf::from(try!(str_of(value, "replay"))),
+    };
+    if game.players.len() != game.rankings.len() ||
+       game.players.iter().any(|&ix| ix >= n_bots) ||
+       game.failed.iter().any(|ix| !game.players.contains(ix)) {
+        return Err(err("game does not match the population"));
+    }
+    Ok(game)
+}
+
+fn strings(items: &[String]) -> Value
+{
+    Value::Array(items.iter().map(|s| Value::String(s.clone())).collect())
+}
+
+fn settings_to_json(settings: &Settings) -> Value
+{
+    let sizes = settings.map_sizes
+                        .iter()
+                        .map(|&s| s as usize)
+                        .collect::<Vec<_>>();
+    object(vec![("players",
+                 indices(&[settings.n_players.0, settings.n_players.1])),
+                ("map_sizes", indices(&sizes)),
+                ("max_failures", Value::Number(settings.max_failures as f64)),
+                ("rating_systems", strings(&settings.rating_systems)),
+                ("matchmaking", Value::String(settings.matchmaking.clone())),
+                ("engine", Value::String(settings.engine.clone()))])
+}
+
+fn settings_from_json(value: &Value) -> Result<Settings, Error>
+{
+    let players = try!(indices_of(value, "players"));
+    if players.len() != 2 {
+        return Err(err("field 'players' is not a range"));
+    }
+    let rating_systems = try!(try!(array_of(value, "rating_systems"))
+                                  .iter()
+                                  .map(|v| {
+                                      v.as_str()
+                                       .map(|s| s.to_owned())
+                                       .ok_or_else(|| {
+                                           err("bad rating system")
+                                       })
+                                  })
+                                  .collect::<Result<Vec<_>, _>>());
+    Ok(Settings {
+        n_players: (players[0], players[1]),
+        map_sizes: try!(indices_of(value, "map_sizes"))
+                       .into_iter()
+                       .map(|s| s as i16)
+                       .collect(),
+        max_failures: try!(int_of(value, "max_failures")) as usize,
+        rating_systems: rating_systems,
+        matchmaking: try!(str_of(value, "matchmaking")).to_owned(),
+        engine: try!(str_of(value, "engine")).to_owned(),
+    })
+}
+
+impl Checkpoint
+{
+    pub fn new(bots: Vec<Entry>, seed: usize) -> Self
+    {
+        Checkpoint {
+            bots: bots,
+            games: Vec::new(),
+            seed: seed,
+            generation: 0,
+            round_size: 1,
+            settings: Settings::default(),
+        }
+    }
+
+    pub fn load<P>(path: P) -> Result<Self, Error>
+        where P: AsRef<Path>
+    {
+        let mut input = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut input)));
+        let root = try!(json::parse(&input)
+                            .map_err(|e| Error::Format(e.to_string())));
+        if try!(int_of(&root, "version")) != VERSION {
+            return Err(err("unsupported checkpoint version"));
+        }
+        let bots = try!(try!(array_of(&root, "bots"))
+                            .iter()
+                            .map(entry_from_json)
+                            .collect::<Result<Vec<_>, _>>());
+        let games = try!(try!(array_of(&root, "games"))
+                             .iter()
+                             .map(|g| game_from_json(g, bots.len()))
+                             .collect::<Result<Vec<_>, _>>());
+        let round_size = try!(int_of(&root, "round_size"));
+        if round_size < 1 {
+            return Err(err("round size must be positive"));
+        }
+        let generation = match root.get("generation") {
+            Some(_) => try!(int_of(&root, "generation")) as usize,
+            None => 0,
+        };
+        Ok(Checkpoint {
+            bots: bots,
+            games: games,
+            seed: try!(seed_of(&root, "seed")) as usize,
+            generation: generation,
+            round_size: round_size as usize,
+            settings: try!(settings_from_json(try!(field(&root, "settings")))),
+        })
+    }
+
+    /// Writes the checkpoint to `path`.  The file is first written next to
+    /// `path` and then renamed over it so that an interruption never leaves
+    /// a half written checkpoint behind.
+    pub fn save<P>(&self, path: P) -> Result<(), Error>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let root = object(vec![("version", Value::Number(VERSION as f64)),
+                               ("seed", seed(self.seed as u64)),
+                               ("generation",
+                                Value::Number(self.generation as f64)),
+                               ("round_size",
+                                Value::Number(self.round_size as f64)),
+                               ("settings", settings_to_json(&self.settings)),
+                               ("bots",
+                                Value::Array(self.bots
+                                                 .iter()
+                                                 .map(entry_to_json)
+                                                 .collect())),
+                               ("games",
+                                Value::Array(self.games
+                                                 .iter()
+                                                 .map(game_to_json)
+                                                 .collect()))]);
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(write!(file, "{}\n", root));
+        }
+        try!(fs::rename(&tmp_path, path));
+        Ok(())
+    }
+}
+
+/// The fixtures the tests of the runner share.
+#[cfg(test)]
+pub mod fixture
+{
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rating::Skill;
+    use runner::Bot;
+    use super::{Checkpoint, Entry, Game};
+
+    static N_TEMP_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+    /// A directory of its own for a test, removed with everything in it
+    /// when dropped.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir
+    {
+        pub fn new(name: &str) -> Self
+        {
+            let n = N_TEMP_DIRS.fetch_add(1, Ordering::SeqCst);
+            let dir = env::temp_dir().join(format!("ua-test-{}-{}-{}",
+                                                   name,
+                                                   process::id(),
+                                                   n));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        pub fn path(&self) -> &Path
+        {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir
+    {
+        fn drop(&mut self)
+        {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A bot with an executable that exists but is never played.
+    pub fn bot() -> Bot
+    {
+        Bot::new("/bin/sh").unwrap()
+    }
+
+    /// A population of bots rated by `bbt` with the given `mu`s.
+    pub fn population(mus: &[f64]) -> Checkpoint
+    {
+        let bots = mus.iter()
+                      .map(|&mu| {
+                          let mut entry = Entry::new(bot());
+                          entry.ratings.insert("bbt".to_owned(),
+                                               Skill {
+                                                   mu: mu,
+                                                   sigma: 1.0,
+                                                   volatility: 0.0,
+                                               });
+                          entry
+                      })
+                      .collect();
+        Checkpoint::new(bots, 1)
+    }
+
+    /// A game the `players` finished in their seat order.
+    pub fn game(players: Vec<usize>) -> Game
+    {
+        Game {
+            width: 25,
+            height: 25,
+            seed: 1,
+            rankings: (1..players.len() + 1).collect(),
+            players: players,
+            failed: vec![],
+            hlt_path: PathBuf::from("1.hlt"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rating::{Bbt, RatingSystem, Skill};
+    use super::{Checkpoint, Entry, Settings};
+    use super::fixture::{self, TempDir};
+
+    #[test]
+    fn test_round_trip()
+    {
+        let mut bot = fixture::bot();
+        bot.brain("lone").set_param("weight", 42.5);
+        let mut entry = Entry::new(bot);
+        let skill = Skill {
+            mu: 27.0,
+            sigma: 4.5,
+            volatility: 0.0,
+        };
+        entry.ratings.insert("bbt".to_owned(), skill.clone());
+        entry.initial_ratings.insert("bbt".to_owned(),
+                                     Skill { mu: 20.0, ..skill.clone() });
+        entry.failures = 1;
+        entry.name = Some("lone".to_owned());
+        let mut state = Checkpoint::new(vec![entry.clone(),
+                                             Entry::new(entry.bot.clone())],
+                                        (1 << 53) + 1);
+        state.round_size = 3;
+        state.settings = Settings {
+            n_players: (2, 4),
+            map_sizes: vec![20, 30],
+            max_failures: 2,
+            rating_systems: vec!["bbt:beta=4".to_owned(),
+                                 "elo:k=16".to_owned()],
+            matchmaking: "swiss".to_owned(),
+            engine: "in-process".to_owned(),
+        };
+        let mut game = fixture::game(vec![1, 0]);
+        game.width = 30;
+        game.seed = ::std::u64::MAX;
+        game.rankings = vec![2, 1];
+        game.failed = vec![1];
+        state.games.push(game);
+        let dir = TempDir::new("checkpoint");
+        let path = dir.path().join("tournament.json");
+        state.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.games, state.games);
+        assert_eq!(loaded.generation, 0);
+        assert_eq!(loaded.seed, (1 << 53) + 1);
+        assert_eq!(loaded.round_size, 3);
+        assert_eq!(loaded.settings, state.settings);
+        assert_eq!(loaded.settings.mismatch(&state.settings), None);
+        let mut other = state.settings.clone();
+        other.map_sizes = vec![40];
+        assert_eq!(state.settings.mismatch(&other),
+                   Some("map sizes 40 instead of 20,30".to_owned()));
+        assert_eq!(loaded.bots.len(), 2);
+        assert_eq!(loaded.bots[0].bot.brain_name(), Some("lone"));
+        assert_eq!(loaded.bots[0].bot.params()["weight"], 42.5);
+        assert_eq!(loaded.bots[0].ratings["bbt"], skill);
+        assert_eq!(loaded.bots[0].initial_ratings["bbt"].mu, 20.0);
+        assert_eq!(loaded.bots[0].failures, 1);
+        assert_eq!(loaded.bots[0].name, Some("lone".to_owned()));
+        assert_eq!(loaded.bots[1].name, None);
+        let bbt = Bbt { beta: 1.0 };
+        assert_eq!(loaded.bots[1].skill(&bbt), bbt.initial());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Optimising the parameters of the bots with a simple genetic algorithm.
+//!
+//! Every generation the population plays a tournament.  The bots with the
+//! lowest `mu` are then culled and replaced by the offspring of the
+//! survivors.  A child takes each parameter from either of its parents and
+//! the value is perturbed with Gaussian noise proportional to the range of
+//! the parameter.
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+
+use ua::params::{ParamFile, Params, Schema};
+
+use checkpoint::{Checkpoint, Entry};
+use runner::{Env, Error};
+use tournament;
+
+#[derive(Clone, Debug)]
+pub struct Config
+{
+    pub n_generations: usize,
+    /// The fraction of the population that survives to the next generation
+    pub survival: f32,
+    /// The deviation of the mutations relative to the parameter ranges
+    pub mutation: f32,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Config {
+            n_generations: 20,
+            survival: 0.5,
+            mutation: 0.1,
+        }
+    }
+}
+
+fn crossover<R: Rng>(a: &Params,
+                     b: &Params,
+                     schema: &Schema,
+                     mutation: f32,
+                     rng: &mut R)
+    -> Params
+{
+    let mut child = Params::new();
+    for spec in schema.specs() {
+        let parent = if rng.gen::<bool>() { a } else { b };
+        let mut value = schema.value(parent, &spec.name);
+        if spec.min < spec.max && mutation > 0.0 {
+            let sd = (mutation * (spec.max - spec.min)) as f64;
+            value += Normal::new(0.0, sd).ind_sample(rng) as f32;
+            value = value.max(spec.min).min(spec.max);
+        }
+        child.insert(spec.name.clone(), value);
+    }
+    child
+}
+
+/// Replaces the quarantined bots and those with the lowest `mu` with the
+/// offspring of the others.  The survivors keep their ratings, which become
+/// their initial ratings for the next generation, and the offspring start
+/// unrated.  Fails if every bot has been quarantined.
+pub fn breed<R: Rng>(state: &mut Checkpoint,
+                     schema: &Schema,
+                     tournament: &tournament::Config,
+                     config: &Config,
+                     rng: &mut R)
+    -> Result<(), Error>
+{
+    let ranking = tournament::ranking(state, tournament);
+    let n_bots = ranking.len();
+    let n_healthy = ranking.iter()
+                           .take_while(|&&ix| {
+                               !tournament.is_quarantined(&state.bots[ix])
+                           })
+                           .count();
+    if n_healthy == 0 {
+        return Err(Error::Runtime("every bot has been quarantined so there \
+                                   is nobody to breed from"
+                                      .to_owned()));
+    }
+    let n_survivors = ((n_bots as f32 * config.survival).round() as usize)
+                          .max(1)
+                          .min(n_healthy);
+    let survivors = ranking[..n_survivors]
+                        .iter()
+                        .map(|&ix| {
+                            let mut entry = state.bots[ix].clone();
+                            entry.initial_ratings = entry.ratings.clone();
+                            entry
+                        })
+                        .collect::<Vec<_>>();
+    let mut bots = survivors.clone();
+    while bots.len() < n_bots {
+        let a = &rng.choose(&survivors).unwrap().bot;
+        let b = &rng.choose(&survivors).unwrap().bot;
+        let params = crossover(a.params(),
+                               b.params(),
+                               schema,
+                               config.mutation,
+                               rng);
+        let mut child = a.clone();
+        child.set_params(&params);
+        bots.push(Entry::new(child));
+    }
+    state.bots = bots;
+    state.games.clear();
+    state.generation += 1;
+    Ok(())
+}
+
+fn print_best(state: &Checkpoint, tournament: &tournament::Config)
+{
+    let best = &state.bots[tournament::ranking(state, tournament)[0]];
+    let skill = best.skill(tournament.primary_rating());
+    println!("Generation {}, best mu = {:.*}, sigma = {:.*}",
+             state.generation + 1,
+             2,
+             skill.mu,
+             2,
+             skill.sigma);
+    let params = best.bot.params().clone();
+    let mut file = ParamFile::new();
+    match best.bot.brain_name() {
+        Some(brain) => {
+            file.sections.insert(brain.to_owned(), params);
+        }
+        None => file.global = params,
+    }
+    print!("{}", file);
+}
+
+/// Evolves the population in `state` until it has been through
+/// `config.n_generations` generations, each rated by a tournament of
+/// `tournament.n_games` games.  The best parameters of each generation are
+/// printed in the format of a parameter file.
+pub fn run(env: &Env,
+           state: &mut Checkpoint,
+           schema: &Schema,
+           tournament: &tournament::Config,
+           config: &Config)
+    -> Result<(), Error>
+{
+    let pool = try!(tournament.pool(env));
+    let checkpoint_path = tournament.checkpoint_path(env);
+    println!("Master seed {}", state.seed);
+    while state.generation < config.n_generations {
+        try!(tournament::play(&pool,
+                              state,
+                              tournament.n_games,
+                              tournament,
+                              &checkpoint_path));
+        print_best(state, tournament);
+        if state.generation + 1 == config.n_generations {
+            break;
+        }
+        let mut rng = tournament::derive_rng(state, tournament::BREED_STREAM);
+        try!(breed(state, schema, tournament, config, &mut rng));
+        try!(state.save(&checkpoint_path));
+    }
+    tournament::write_report(state, tournament)
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{SeedableRng, StdRng};
+
+    use ua::params::Schema;
+
+    use checkpoint::{Checkpoint, Entry};
+    use checkpoint::fixture::{bot, population};
+    use rating::RatingSystem;
+    use super::{Config, breed};
+    use tournament;
+
+    #[test]
+    fn test_breed_keeps_the_best_and_stays_in_range()
+    {
+        let schema = Schema::new()
+                         .param("weight", 50.0, 0.0, 100.0, "")
+                         .param("factor", 0.5, 0.0, 0.99, "");
+        let seed: &[_] = &[1, 2, 3];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mus = (0..10).map(|ix| ix as f64).collect::<Vec<_>>();
+        let mut state = population(&mus);
+        for entry in state.bots.iter_mut() {
+            entry.bot.set_params(&schema.sample(&mut rng));
+        }
+        let config = tournament::Config::default();
+        // The best bot crashed too often to survive
+        state.bots[9].failures = 2;
+        let best = state.bots[8].bot.params().clone();
+        breed(&mut state,
+              &schema,
+              &config,
+              &Config::default(),
+              &mut rng)
+            .unwrap();
+        assert_eq!(state.generation, 1);
+        assert_eq!(state.bots.len(), 10);
+        assert_eq!(state.bots[0].bot.params(), &best);
+        assert_eq!(state.bots[0].ratings["bbt"].mu, 8.0);
+        assert_eq!(state.bots[0].initial_ratings["bbt"].mu, 8.0);
+        for entry in state.bots[5..].iter() {
+            assert_eq!(entry.skill(config.primary_rating()),
+                       config.primary_rating().initial());
+            assert_eq!(entry.failures, 0);
+            assert_eq!(schema.check(entry.bot.params()), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_breed_fails_when_every_bot_is_quarantined()
+    {
+        let schema = Schema::new().param("weight", 50.0, 0.0, 100.0, "");
+        let seed: &[_] = &[1];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut entry = Entry::new(bot());
+        entry.failures = 2;
+        let mut state = Checkpoint::new(vec![entry; 4], 1);
+        assert!(breed(&mut state,
+                      &schema,
+                      &tournament::Config::default(),
+                      &Config::default(),
+                      &mut rng)
+                    .is_err());
+        assert_eq!(state.generation, 0);
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Playing a candidate bot against the archived releases.
+//!
+//! `bin/archive.sh` copies every release into `bots/MyBot-$TAG` where the
+//! tag is from `git describe` so the releases are ordered by their tags.  The
+//! gauntlet plays the candidate against each of them both one on one and in
+//! free-for-all games where the other seats are taken by copies of the
+//! release.  Every release is played on the same maps with the same seeds.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cache::Cache;
+use pool::{Job, Pool};
+use runner::{Bot, Env, Error, External};
+
+const ARCHIVE_PREFIX: &'static str = "MyBot-";
+
+/// The z-score of the two-sided 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+#[derive(Clone, Debug)]
+pub struct Config
+{
+    /// The directory holding the archived releases
+    pub archive_dir: PathBuf,
+    /// The number of games of each format against each release
+    pub n_games: usize,
+    /// The seed of the first map; the rest follow in sequence
+    pub base_seed: usize,
+    /// The side lengths of the square maps the games cycle through
+    pub map_sizes: Vec<i16>,
+    /// The number of players in the free-for-all games
+    pub n_ffa_players: usize,
+    /// The number of games played at the same time
+    pub n_workers: usize,
+    /// Whether to reuse the outcomes of the games played before
+    pub cache: bool,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Config {
+            archive_dir: PathBuf::from("./bots"),
+            n_games: 20,
+            base_seed: 1,
+            map_sizes: vec![25, 30, 35, 40],
+            n_ffa_players: 4,
+            n_workers: 1,
+            cache: true,
+        }
+    }
+}
+
+/// The wins of the candidate in one format against one release.
+#[derive(Clone, Debug)]
+pub struct Score
+{
+    pub n_players: usize,
+    pub wins: usize,
+    pub games: usize,
+    /// The games in which the candidate crashed or timed out
+    pub failures: usize,
+}
+
+impl Score
+{
+    pub fn win_rate(&self) -> f64
+    {
+        self.wins as f64 / self.games as f64
+    }
+
+    /// The win rate the candidate would have if it played as well as the
+    /// release.
+    pub fn par(&self) -> f64
+    {
+        1.0 / self.n_players as f64
+    }
+
+    /// The 95% Wilson score interval of the win rate.
+    pub fn interval(&self) -> (f64, f64)
+    {
+        wilson_interval(self.wins, self.games, Z_95)
+    }
+
+    /// Whether the candidate is worse than the release beyond doubt, that
+    /// is the whole interval is below par.
+    pub fn is_worse(&self) -> bool
+    {
+        self.interval().1 < self.par()
+    }
+}
+
+fn wilson_interval(wins: usize, games: usize, z: f64) -> (f64, f64)
+{
+    if games == 0 {
+        return (0.0, 1.0);
+    }
+    let n = games as f64;
+    let p = wins as f64 / n;
+    let z2 = z * z;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let spread = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() /
+                 (1.0 + z2 / n);
+    ((center - spread).max(0.0), (center + spread).min(1.0))
+}
+
+/// A part of a release tag; the numbers in a tag are compared by value so
+/// that `v10` comes after `v9`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum TagPart
+{
+    Number(u64),
+    Text(String),
+}
+
+fn tag_key(tag: &str) -> Vec<TagPart>
+{
+    let mut key = Vec::new();
+    let mut chars = tag.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let is_digit = c.is_digit(10);
+        let mut part = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_digit(10) != is_digit {
+                break;
+            }
+            part.push(c);
+            chars.next();
+        }
+        key.push(match part.parse() {
+            Ok(n) if is_digit => TagPart::Number(n),
+            _ => TagPart::Text(part),
+        });
+    }
+    key
+}
+
+/// The archived releases in `dir` from the oldest to the newest by their
+/// tags.  The modification times are not used since copying the archive
+/// does not keep them.
+pub fn archived_bots(dir: &Path) -> Result<Vec<PathBuf>, Error>
+{
+    let mut bots = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        let entry = try!(entry);
+        let tag = match entry.file_name().to_str() {
+            Some(name) if name.starts_with(ARCHIVE_PREFIX) => {
+                tag_key(&name[ARCHIVE_PREFIX.len()..])
+            }
+            _ => continue,
+        };
+        if try!(entry.metadata()).is_file() {
+            bots.push((tag, entry.path()));
+        }
+    }
+    bots.sort();
+    Ok(bots.into_iter().map(|(_, path)| path).collect())
+}
+
+fn play(pool: &Pool,
+        candidate: &Bot,
+        release: &Bot,
+        n_players: usize,
+        config: &Config)
+    -> Result<Score, Error>
+{
+    // The candidate changes its seat from game to game so that no seat is
+    // favoured.  The releases are executables so the games are played by
+    // the environment.
+    let jobs = (0..config.n_games)
+                   .map(|ix| {
+                       let sizes = &config.map_sizes;
+                       let size = sizes[ix % sizes.len()];
+                       let mut bots = vec![release.clone(); n_players];
+                       bots[ix % n_players] = candidate.clone();
+                       Job {
+                           width: size,
+                           height: size,
+                           seed: Some(config.base_seed + ix),
+                           bots: bots,
+                           engine: Arc::new(External),
+                       }
+                   })
+                   .collect::<Vec<_>>();
+    let mut wins = 0;
+    let mut failures = 0;
+    for (ix, outcome) in pool.run_all(&jobs).into_iter().enumerate() {
+        let outcome = try!(outcome);
+        let seat = ix % n_players;
+        if outcome.rankings[seat] == 1 {
+            wins += 1;
+        }
+        if outcome.failures.iter().any(|f| f.player == seat) {
+            failures += 1;
+        }
+    }
+    Ok(Score {
+        n_players: n_players,
+        wins: wins,
+        games: config.n_games,
+        failures: failures,
+    })
+}
+
+fn print_score(release: &Path, score: &Score)
+{
+    let (lo, hi) = score.interval();
+    println!("{:<30} {}p {:>4}/{:<4} {:>5.1}% [{:>5.1}%, {:>5.1}%]{}{}",
+             release.file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+             score.n_players,
+             score.wins,
+             score.games,
+             100.0 * score.win_rate(),
+             100.0 * lo,
+             100.0 * hi,
+             if score.failures > 0 {
+                 format!("  {} failed", score.failures)
+             } else {
+                 String::new()
+             },
+             if score.is_worse() { "  WORSE" } else { "" });
+}
+
+/// Plays `candidate` against every archived release and prints its win
+/// rates.  Returns whether the candidate is no worse than the newest
+/// release in every format.
+pub fn run(env: &Env, candidate: &Bot, config: &Config) -> Result<bool, Error>
+{
+    if config.n_ffa_players < 3 {
+        return Err(Error::Runtime("free-for-all games need at least three \
+                                   players"
+                                      .to_owned()));
+    }
+    let releases = try!(archived_bots(&config.archive_dir));
+    if releases.is_empty() {
+        return Err(Error::Environment(format!("no releases in {}",
+                                              config.archive_dir.display())));
+    }
+    let mut pool = try!(Pool::new(env, config.n_workers));
+    if config.cache {
+        pool = pool.with_cache(try!(Cache::in_work_dir(env)));
+    }
+    let mut passed = true;
+    for (ix, path) in releases.iter().enumerate() {
+        let release = try!(Bot::new(path));
+        let is_newest = ix + 1 == releases.len();
+        for &n_players in [2, config.n_ffa_players].iter() {
+            let score = try!(play(&pool,
+                                  candidate,
+                                  &release,
+                                  n_players,
+                                  config));
+            print_score(path, &score);
+            if is_newest && score.is_worse() {
+                passed = false;
+            }
+        }
+    }
+    Ok(passed)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::File;
+
+    use checkpoint::fixture::TempDir;
+    use super::{Score, Z_95, archived_bots, wilson_interval};
+
+    #[test]
+    fn test_archived_bots_are_ordered_by_tag()
+    {
+        let dir = TempDir::new("archive");
+        for tag in ["v10", "v9", "v9-2-gabc123", "v9-10-g123abc"].iter() {
+            File::create(dir.path().join(format!("MyBot-{}", tag))).unwrap();
+        }
+        File::create(dir.path().join("notes.txt")).unwrap();
+        let names = archived_bots(dir.path())
+                        .unwrap()
+                        .iter()
+                        .map(|p| p.file_name().unwrap().to_owned())
+                        .collect::<Vec<_>>();
+        assert_eq!(names,
+                   vec!["MyBot-v9",
+                        "MyBot-v9-2-gabc123",
+                        "MyBot-v9-10-g123abc",
+                        "MyBot-v10"]);
+    }
+
+    #[test]
+    fn test_wilson_interval()
+    {
+        let (lo, hi) = wilson_interval(10, 20, Z_95);
+        assert!((lo - 0.2993).abs() < 1e-3);
+        assert!((hi - 0.7007).abs() < 1e-3);
+        assert_eq!(wilson_interval(0, 0, Z_95), (0.0, 1.0));
+        let (lo, hi) = wilson_interval(0, 20, Z_95);
+        assert_eq!(lo, 0.0);
+        assert!(hi > 0.0 && hi < 0.2);
+    }
+
+    #[test]
+    fn test_is_worse()
+    {
+        let score = |n_players, wins| {
+            Score {
+                n_players: n_players,
+                wins: wins,
+                games: 40,
+                failures: 0,
+            }
+        };
+        assert!(score(2, 8).is_worse());
+        assert!(!score(2, 15).is_worse());
+        assert!(!score(4, 6).is_worse());
+        assert!(score(4, 1).is_worse());
+    }
+}
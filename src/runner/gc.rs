@@ -0,0 +1,334 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Cleaning up the working directory after tournaments.
+//!
+//! Every match leaves a replay in the working directory and every distinct
+//! parameter set a bot script.  Given the checkpoint of a tournament the
+//! collector keeps the replays of the games played by the best bots and of
+//! the upsets, that is the games won by a bot rated lower than at least
+//! half of the bots it beat, and the scripts of the bots in the population.
+//! Every other replay and script in the working directory goes, so those of
+//! earlier generations, other tournaments and gauntlets go too.  The cached
+//! outcomes of the games whose replays go are dropped with them; the rest
+//! of the cache and the logs are left alone.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cache::{self, CACHE_DIR};
+use checkpoint::{Checkpoint, Game};
+use runner::{self, Env, Error};
+use tournament;
+
+#[derive(Clone, Debug)]
+pub struct Config
+{
+    /// The number of the best bots whose games are kept
+    pub keep_top: usize,
+    /// Whether to keep the games that ended in an upset
+    pub keep_upsets: bool,
+    /// Only tell what would be removed
+    pub dry_run: bool,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Config {
+            keep_top: 10,
+            keep_upsets: true,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Kind
+{
+    Replay,
+    Script,
+    Log,
+    Cache,
+    Other,
+}
+
+impl Kind
+{
+    fn name(&self) -> &'static str
+    {
+        match *self {
+            Kind::Replay => "replays",
+            Kind::Script => "scripts",
+            Kind::Log => "logs",
+            Kind::Cache => "cache",
+            Kind::Other => "other",
+        }
+    }
+}
+
+fn is_script_name(name: &str) -> bool
+{
+    name.len() == 32 && name.chars().all(|c| c.is_digit(16))
+}
+
+fn kind_of(path: &Path, in_cache: bool) -> Kind
+{
+    if in_cache {
+        return Kind::Cache;
+    }
+    let name = path.file_name()
+                   .and_then(|n| n.to_str())
+                   .unwrap_or("");
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("hlt") => Kind::Replay,
+        Some("log") => Kind::Log,
+        _ if is_script_name(name) => Kind::Script,
+        _ => Kind::Other,
+    }
+}
+
+/// A file in the working directory.
+struct File_
+{
+    path: PathBuf,
+    kind: Kind,
+    size: u64,
+}
+
+fn walk(dir: &Path, in_cache: bool, files: &mut Vec<File_>)
+    -> Result<(), Error>
+{
+    for entry in try!(fs::read_dir(dir)) {
+        let entry = try!(entry);
+        let metadata = try!(entry.metadata());
+        let path = entry.path();
+        if metadata.is_dir() {
+            let is_cache = path.file_name().map(|n| n == CACHE_DIR) ==
+                           Some(true);
+            try!(walk(&path, in_cache || is_cache, files));
+        } else {
+            files.push(File_ {
+                kind: kind_of(&path, in_cache),
+                path: path,
+                size: metadata.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether `game` was won by a bot rated lower than at least half of the
+/// bots it beat.
+fn is_upset(game: &Game, mus: &[f64]) -> bool
+{
+    let winner = match game.rankings
+                           .iter()
+                           .position(|&rank| rank == 1) {
+        Some(seat) => game.players[seat],
+        None => return false,
+    };
+    if game.failed.contains(&winner) {
+        return false;
+    }
+    let others = game.players
+                     .iter()
+                     .filter(|&&ix| ix != winner)
+                     .collect::<Vec<_>>();
+    let n_better = others.iter().filter(|&&&ix| mus[ix] > mus[winner]).count();
+    !others.is_empty() && 2 * n_better >= others.len()
+}
+
+/// The replays of the games worth keeping.
+fn kept_replays(state: &Checkpoint,
+                tournament: &tournament::Config,
+                config: &Config)
+    -> HashSet<PathBuf>
+{
+    let system = tournament.primary_rating();
+    let mus = state.bots
+                   .iter()
+                   .map(|e| e.skill(system).mu)
+                   .collect::<Vec<_>>();
+    let top = tournament::ranking(state, tournament)
+                  .into_iter()
+                  .take(config.keep_top)
+                  .collect::<HashSet<_>>();
+    state.games
+         .iter()
+         .filter(|game| {
+             game.players.iter().any(|ix| top.contains(ix)) ||
+             config.keep_upsets && is_upset(game, &mus)
+         })
+         .map(|game| game.hlt_path.clone())
+         .collect()
+}
+
+/// The scripts of the population in each directory holding scripts.
+fn live_scripts(env: &Env, state: &Checkpoint, files: &[File_])
+    -> Result<HashSet<PathBuf>, Error>
+{
+    let dirs = files.iter()
+                    .filter(|f| f.kind == Kind::Script)
+                    .filter_map(|f| f.path.parent())
+                    .collect::<HashSet<_>>();
+    let mut live = HashSet::new();
+    for dir in dirs {
+        let dir_env = try!(env.in_dir(dir));
+        for entry in state.bots.iter() {
+            let name = try!(runner::script_name(&dir_env, &entry.bot));
+            live.insert(dir.join(name));
+        }
+    }
+    Ok(live)
+}
+
+fn human_size(bytes: u64) -> String
+{
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.*} {}", 1, size, units[unit])
+    }
+}
+
+fn usage(files: &[&File_]) -> BTreeMap<Kind, (usize, u64)>
+{
+    let mut usage = BTreeMap::new();
+    for file in files.iter() {
+        let entry = usage.entry(file.kind).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += file.size;
+    }
+    usage
+}
+
+fn print_usage(before: &BTreeMap<Kind, (usize, u64)>,
+               after: &BTreeMap<Kind, (usize, u64)>)
+{
+    println!("{:<10} {:>20} {:>20}", "", "before", "after");
+    for (kind, &(n_before, size_before)) in before.iter() {
+        let (n_after, size_after) = after.get(kind).cloned().unwrap_or((0, 0));
+        println!("{:<10} {:>20} {:>20}",
+                 kind.name(),
+                 format!("{} ({})", human_size(size_before), n_before),
+                 format!("{} ({})", human_size(size_after), n_after));
+    }
+}
+
+/// Removes the replays and the bot scripts not worth keeping from the
+/// working directory of `env` and prints the disk usage before and after.
+pub fn run(env: &Env,
+           state: &Checkpoint,
+           tournament: &tournament::Config,
+           config: &Config)
+    -> Result<(), Error>
+{
+    let mut files = Vec::new();
+    try!(walk(env.work_dir(), false, &mut files));
+    let kept = kept_replays(state, tournament, config);
+    let live = try!(live_scripts(env, state, &files));
+    let removed = files.iter()
+                       .filter(|f| {
+                           f.kind == Kind::Replay && !kept.contains(&f.path)
+                       })
+                       .map(|f| f.path.clone())
+                       .collect::<HashSet<_>>();
+    let (garbage, rest): (Vec<&File_>, Vec<&File_>) =
+        files.iter().partition(|f| {
+            match f.kind {
+                Kind::Replay => removed.contains(&f.path),
+                Kind::Script => !live.contains(&f.path),
+                Kind::Cache => {
+                    cache::replay_of(&f.path)
+                        .map(|replay| removed.contains(&replay)) ==
+                    Some(true)
+                }
+                _ => false,
+            }
+        });
+    for file in garbage.iter() {
+        if config.dry_run {
+            println!("Would remove {}", file.path.display());
+        } else {
+            try!(fs::remove_file(&file.path));
+        }
+    }
+    let all = files.iter().collect::<Vec<_>>();
+    print_usage(&usage(&all), &usage(&rest));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::path::{Path, PathBuf};
+
+    use checkpoint::Game;
+    use super::{Kind, human_size, is_upset, kind_of};
+
+    #[test]
+    fn test_kind_of()
+    {
+        let script = "0123456789abcdef0123456789abcdef";
+        assert_eq!(kind_of(Path::new("/w/123.hlt"), false), Kind::Replay);
+        assert_eq!(kind_of(Path::new("/w/2-42.log"), false), Kind::Log);
+        assert_eq!(kind_of(&Path::new("/w").join(script), false),
+                   Kind::Script);
+        assert_eq!(kind_of(Path::new("/w/tournament.json"), false),
+                   Kind::Other);
+        assert_eq!(kind_of(Path::new("/w/cache/a.json"), true), Kind::Cache);
+    }
+
+    #[test]
+    fn test_is_upset()
+    {
+        let game = |players: Vec<usize>, rankings| {
+            Game {
+                width: 25,
+                height: 25,
+                seed: 1,
+                players: players,
+                rankings: rankings,
+                failed: vec![],
+                hlt_path: PathBuf::from("1.hlt"),
+            }
+        };
+        let mus = [10.0, 20.0, 30.0];
+        assert!(is_upset(&game(vec![0, 1], vec![1, 2]), &mus));
+        assert!(!is_upset(&game(vec![0, 1], vec![2, 1]), &mus));
+        assert!(is_upset(&game(vec![1, 0, 2], vec![1, 2, 3]), &mus));
+        assert!(!is_upset(&game(vec![2, 0, 1], vec![1, 2, 3]), &mus));
+    }
+
+    #[test]
+    fn test_human_size()
+    {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
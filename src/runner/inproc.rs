@@ -0,0 +1,284 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Playing matches in-process with the brains of the `ua` library.
+//!
+//! Instead of running the halite environment and the bot executables the
+//! engine simulates the game with `ua::sim` and ticks the brains directly.
+//! Only hosted bots (see `Bot::hosted`) naming a single brain can be played
+//! this way; a bot with an executable of its own or a fallback chain is
+//! refused rather than quietly played by some other brain.
+//!
+//! The maps come from `ua::sim::generate_map` so they are not the maps the
+//! environment would generate for the same seed.  A brain that panics or
+//! takes longer than the environment allows for a turn is dropped from the
+//! game like the environment drops a bot that crashes or times out.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::{self, Rng};
+
+use ua::{Deadline, Log};
+use ua::brain::{self, Brain, DEFAULT_TURN_BUDGET_MS, Mold, panic_message};
+use ua::params::Params;
+use ua::replay::Replay;
+use ua::sim::{self, Game};
+use ua::world::Tag;
+
+use runner::{Bot, Engine, Env, Error, Failure, Match, Outcome};
+
+/// The time the environment allows for a turn.
+const TURN_LIMIT_MS: u64 = 1000;
+
+fn millis(duration: Duration) -> u64
+{
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+/// Plays the matches with the brains in the same process.
+#[derive(Clone, Debug)]
+pub struct InProcess
+{
+    /// The time each brain is given to decide its moves
+    pub budget: Duration,
+    /// The time after which a brain is dropped from the game
+    pub turn_limit: Duration,
+}
+
+impl InProcess
+{
+    pub fn new() -> Self
+    {
+        InProcess {
+            budget: Duration::from_millis(DEFAULT_TURN_BUDGET_MS),
+            turn_limit: Duration::from_millis(TURN_LIMIT_MS),
+        }
+    }
+}
+
+/// The brain `bot` is played by.
+fn brain_of(bot: &Bot) -> Result<&str, Error>
+{
+    let refuse = |why: &str| {
+        Err(Error::Environment(format!("bot {} cannot be played \
+                                        in-process: {}",
+                                       bot,
+                                       why)))
+    };
+    if !bot.is_hosted() {
+        return refuse("it has an executable of its own");
+    }
+    match bot.brain_name() {
+        None => refuse("no brain given"),
+        Some(name) if name.contains(',') => {
+            refuse("fallback chains are not supported")
+        }
+        Some(name) => Ok(name),
+    }
+}
+
+/// Reanimates `mold` with the parameters of `bot` as the player `tag`.
+fn reanimate(mold: &Mold, bot: &Bot, tag: Tag, sim: &Game, log: &mut Log)
+    -> Result<Box<Brain>, String>
+{
+    let schema = mold.schema();
+    let params = bot.params()
+                    .iter()
+                    .filter(|&(k, _)| schema.spec(k).is_some())
+                    .map(|(k, v)| (k.clone(), *v))
+                    .collect::<Params>();
+    let environment = sim.environment_for(tag);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        mold.reanimate(&params, environment, sim.state(), log)
+    }));
+    result.map_err(|payload| {
+        format!("brain {} panicked while reanimating: {}",
+                mold.name(),
+                panic_message(&payload))
+    })
+}
+
+impl Engine for InProcess
+{
+    fn name(&self) -> &str
+    {
+        "in-process"
+    }
+
+    fn is_in_process(&self) -> bool
+    {
+        true
+    }
+
+    fn check(&self, bot: &Bot) -> Result<(), Error>
+    {
+        let name = try!(brain_of(bot));
+        brain::mold_of(name).map(|_| ()).map_err(Error::Environment)
+    }
+
+    fn play(&self, game: &Match, env: &Env) -> Result<Outcome, Error>
+    {
+        let bots = game.bots();
+        let n_players = bots.len();
+        let mut molds = Vec::with_capacity(n_players);
+        for bot in bots.iter() {
+            let name = try!(brain_of(bot));
+            molds.push(try!(brain::mold_of(name).map_err(Error::Environment)));
+        }
+        let seed = match game.map_seed() {
+            Some(seed) => seed,
+            None => rand::thread_rng().gen::<u32>() as usize,
+        };
+        let (space, production_map, init_state) =
+            sim::generate_map(game.width(), game.height(), n_players, seed);
+        let mut sim = Game::new(space, production_map, init_state.clone());
+        let mut log = Log::none();
+        let mut failures = Vec::new();
+        let mut brains = Vec::with_capacity(n_players);
+        for (ix, mold) in molds.iter().enumerate() {
+            let tag = (ix + 1) as Tag;
+            match reanimate(&**mold, bots[ix], tag, &sim, &mut log) {
+                Ok(brain) => brains.push(Some(brain)),
+                Err(message) => {
+                    failures.push(Failure {
+                        player: ix,
+                        turn: None,
+                        log_path: None,
+                        log: Some(message),
+                    });
+                    sim.drop_player(tag);
+                    brains.push(None);
+                }
+            }
+        }
+        let names = molds.iter()
+                         .map(|mold| format!("UA_{}", mold.name()))
+                         .collect();
+        let mut replay = Replay::new(sim.environment_for(0), names, init_state);
+        while !sim.is_over() {
+            // The bots count the turns from the first frame they move on
+            let mut state = sim.state().clone();
+            state.turn += 1;
+            let mut moves = Vec::with_capacity(n_players);
+            for (ix, slot) in brains.iter_mut().enumerate() {
+                let tag = (ix + 1) as Tag;
+                let result = match *slot {
+                    Some(ref mut brain) if sim.is_alive(tag) => {
+                        let deadline = Deadline::starting_now(self.budget);
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            brain.tick(&state, &deadline, &mut log)
+                        }));
+                        match result {
+                            Ok(_) if deadline.elapsed() > self.turn_limit => {
+                                Err(format!("brain timed out after {} ms",
+                                            millis(deadline.elapsed())))
+                            }
+                            Ok(actions) => Ok(actions),
+                            Err(payload) => {
+                                Err(format!("brain panicked: {}",
+                                            panic_message(&payload)))
+                            }
+                        }
+                    }
+                    _ => Ok(Vec::new()),
+                };
+                match result {
+                    Ok(actions) => moves.push(actions),
+                    Err(message) => {
+                        failures.push(Failure {
+                            player: ix,
+                            turn: None,
+                            log_path: None,
+                            log: Some(message),
+                        });
+                        sim.drop_player(tag);
+                        *slot = None;
+                        moves.push(Vec::new());
+                    }
+                }
+            }
+            sim.step(&moves);
+            replay.record_turn(&moves, sim.state().clone());
+        }
+        let now = SystemTime::now()
+                      .duration_since(UNIX_EPOCH)
+                      .unwrap_or(Duration::from_secs(0));
+        let hlt_path = env.work_dir().join(format!("{}{:09}-{}.hlt",
+                                                   now.as_secs(),
+                                                   now.subsec_nanos(),
+                                                   seed));
+        try!(replay.save(&hlt_path).map_err(|e| {
+            Error::Format(format!("replay {}: {:?}", hlt_path.display(), e))
+        }));
+        let stats = replay.player_stats();
+        for failure in failures.iter_mut() {
+            failure.turn = stats[failure.player].eliminated;
+        }
+        Ok(Outcome {
+            seed: seed as u64,
+            rankings: sim.rankings(),
+            hlt_path: hlt_path,
+            stats: stats,
+            failures: failures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use ua::space::Space;
+
+    use checkpoint::fixture::{self, TempDir};
+    use runner::{Bot, Engine, Env, External, Match};
+    use super::InProcess;
+
+    #[test]
+    fn test_plays_a_match()
+    {
+        let dir = TempDir::new("inproc");
+        let env = Env::new(dir.path()).unwrap();
+        let simple = Bot::hosted("simple").unwrap();
+        let lone = Bot::hosted("lone_expander").unwrap();
+        let space = Space::with_dims(12, 12);
+        let game = Match::new(&space).seed(3).bot(&simple).bot(&lone);
+        let outcome = InProcess::new().play(&game, &env).unwrap();
+        assert_eq!(outcome.seed, 3);
+        let mut rankings = outcome.rankings.clone();
+        rankings.sort();
+        assert_eq!(rankings, vec![1, 2]);
+        assert_eq!(outcome.stats.len(), 2);
+        assert!(outcome.hlt_path.is_file());
+    }
+
+    #[test]
+    fn test_refuses_bots_it_cannot_host()
+    {
+        let engine = InProcess::new();
+        assert!(engine.check(&Bot::hosted("teddy").unwrap()).is_ok());
+        assert!(engine.check(&Bot::hosted("nobody").unwrap()).is_err());
+        assert!(engine.check(&Bot::hosted("teddy,simple").unwrap()).is_err());
+        let mut release = fixture::bot();
+        assert!(engine.check(&release).is_err());
+        release.brain("teddy");
+        assert!(engine.check(&release).is_err());
+        assert!(External.check(&Bot::hosted("teddy").unwrap()).is_err());
+        assert!(External.check(&release).is_ok());
+    }
+}
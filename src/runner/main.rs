@@ -17,106 +17,824 @@
 
 extern crate bbt;
 extern crate data_encoding;
+extern crate getopts;
 extern crate md5;
 extern crate rand;
 extern crate ua;
 
+mod cache;
+mod checkpoint;
+mod evolve;
+mod gauntlet;
+mod gc;
+mod inproc;
+mod matchmaking;
+mod pool;
+mod rating;
+mod report;
 mod runner;
+mod spec;
+mod tournament;
 
+use std::env;
+use std::io::Write;
+use std::path::PathBuf;
 use std::process;
+use std::str::FromStr;
 
-use ua::space::Space;
-use ua::util::f32_cmp;
-use bbt::Rating;
-use rand::distributions::{IndependentSample, Range};
-use rand::Rng;
+use getopts::{Matches, Options, ParsingStyle};
 
+use ua::space::Space;
 
+use checkpoint::Checkpoint;
 use runner::{Bot, Env};
 
-fn do_match(env: &Env, space: &Space, bots: &mut [(Bot, Rating)])
+const DEFAULT_WORK_DIR: &'static str = "./tmp/runner";
+const DEFAULT_HALITE_EXE: &'static str = "../Environment/halite";
+const DEFAULT_BOT_EXE: &'static str = "./target/release/MyBot";
+
+const USAGE_COMMANDS: &'static str = "
+Commands:
+    match       Play a single game between the given bot executables
+    tournament  Rate a population of bots with randomized parameters
+    evolve      Breed better parameters over generations of tournaments
+    gauntlet    Play a candidate bot against the archived releases
+    gc          Remove the replays and bot scripts not worth keeping
+
+Use COMMAND -h for the options of the command.";
+
+enum Command
+{
+    Match(MatchConfig),
+    Tournament(TournamentConfig),
+    Evolve(EvolveConfig),
+    Gauntlet(GauntletConfig),
+    Gc(GcConfig),
+}
+
+struct Config
+{
+    work_dir: String,
+    halite_exe: String,
+    command: Command,
+}
+
+struct MatchConfig
+{
+    width: i16,
+    height: i16,
+    seed: Option<usize>,
+    bots: Vec<String>,
+}
+
+struct TournamentConfig
+{
+    /// The bot executable unless the bots are hosted
+    bot_exe: Option<String>,
+    brain: Option<String>,
+    /// The spec file of a mixed population replacing the executable
+    bot_spec: Option<PathBuf>,
+    resume: bool,
+    tournament: tournament::Config,
+}
+
+struct EvolveConfig
 {
-    let outcome = {
-        let mut game = runner::Match::new(space);
-        for bot in bots.iter() {
-            game = game.bot(&bot.0);
+    tournament: TournamentConfig,
+    evolve: evolve::Config,
+}
+
+struct GauntletConfig
+{
+    candidate_exe: String,
+    brain: Option<String>,
+    gauntlet: gauntlet::Config,
+}
+
+struct GcConfig
+{
+    tournament: tournament::Config,
+    gc: gc::Config,
+}
+
+enum OptionParsing<T>
+{
+    ShowUsage(String),
+    Config(T),
+}
+
+fn parse_number<T: FromStr>(s: &str, what: &str) -> Result<T, String>
+{
+    s.trim().parse::<T>().map_err(|_| format!("bad {} '{}'", what, s))
+}
+
+/// Parses a pair of numbers separated by `sep` such as "30x20" or "2-6".
+fn parse_pair<T: FromStr>(s: &str,
+                          sep: char,
+                          what: &str)
+    -> Result<(T, T), String>
+{
+    let mut parts = s.splitn(2, sep);
+    let a = try!(parse_number(parts.next().unwrap_or(""), what));
+    let b = try!(parts.next()
+                      .ok_or_else(|| format!("bad {} '{}'", what, s))
+                      .and_then(|p| parse_number(p, what)));
+    Ok((a, b))
+}
+
+fn opt_or<T: FromStr>(matches: &Matches,
+                      name: &str,
+                      default: T,
+                      what: &str)
+    -> Result<T, String>
+{
+    match matches.opt_str(name) {
+        Some(s) => parse_number(&s, what),
+        None => Ok(default),
+    }
+}
+
+/// Parses the comma separated map sizes given with the option `-m`.
+fn map_sizes_or(matches: &Matches, default: &[i16]) -> Result<Vec<i16>, String>
+{
+    let map_sizes = match matches.opt_str("m") {
+        Some(sizes) => {
+            try!(sizes.split(',')
+                      .map(|s| parse_number::<i16>(s, "map size"))
+                      .collect::<Result<Vec<_>, _>>())
         }
-        game.run(&env).unwrap()
+        None => default.to_vec(),
+    };
+    if map_sizes.is_empty() || map_sizes.iter().any(|&s| s < 1) {
+        return Err("bad map sizes".to_owned());
+    }
+    Ok(map_sizes)
+}
+
+fn command_usage(opts: &Options, program: &str, synopsis: &str) -> String
+{
+    opts.usage(&format!("usage: {} [ options ] {}", program, synopsis))
+}
+
+fn parse_match(program: &str,
+               args: &[String])
+    -> Result<OptionParsing<Command>, String>
+{
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this usage information")
+        .optopt("d", "dims", "Dimensions of the map (default 30x30)", "WxH")
+        .optopt("s", "seed", "Seed for the map generator", "SEED");
+    let matches = try!(opts.parse(args).map_err(|e| e.to_string()));
+    if matches.opt_present("h") {
+        return Ok(OptionParsing::ShowUsage(command_usage(&opts,
+                                                         program,
+                                                         "match [ options ] \
+                                                          BOT BOT...")));
+    }
+    let (width, height) = match matches.opt_str("d") {
+        Some(dims) => try!(parse_pair(&dims, 'x', "map dimensions")),
+        None => (30, 30),
+    };
+    if matches.free.len() < 2 {
+        return Err("a match needs at least two bots".to_owned());
+    }
+    Ok(OptionParsing::Config(Command::Match(MatchConfig {
+        width: width,
+        height: height,
+        seed: match matches.opt_str("s") {
+            Some(s) => Some(try!(parse_number(&s, "seed"))),
+            None => None,
+        },
+        bots: matches.free.clone(),
+    })))
+}
+
+/// Adds the options shared by the commands playing tournaments.
+fn tournament_options(opts: &mut Options, games: &str)
+{
+    let defaults = tournament::Config::default();
+    opts.optflag("h", "help", "Display this usage information")
+        .optopt("x",
+                "exe",
+                &format!("The bot executable (default {})", DEFAULT_BOT_EXE),
+                "FILE")
+        .optopt("b", "brain", "The brain the bots use", "NAME")
+        .optopt("n",
+                "population",
+                &format!("Number of bots (default {})",
+                         defaults.population_size),
+                "N")
+        .optopt("g",
+                "games",
+                &format!("Number of games{} (default {})",
+                         games,
+                         defaults.n_games),
+                "N")
+        .optopt("p",
+                "players",
+                &format!("Range of players in a game (default {}-{})",
+                         defaults.n_players.0,
+                         defaults.n_players.1),
+                "MIN-MAX")
+        .optopt("m",
+                "map-sizes",
+                "Side lengths of the square maps (default 25,30,35,40)",
+                "SIZE[,SIZE...]")
+        .optopt("j",
+                "jobs",
+                &format!("Number of games played at once (default {})",
+                         defaults.n_workers),
+                "N")
+        .optopt("s",
+                "seed",
+                "Master seed of the tournament (default random)",
+                "SEED")
+        .optopt("R",
+                "ratings",
+                "Rating systems to rate the bots by; the first one picks \
+                 the games (default bbt)",
+                "SYSTEM[,SYSTEM...]")
+        .optopt("",
+                "matchmaking",
+                &format!("Policy picking the players of the games: sigma, \
+                          random, swiss or round-robin (default {})",
+                         defaults.matchmaking.name()),
+                "POLICY")
+        .optopt("F",
+                "max-failures",
+                &format!("Crashes and timeouts after which a bot is \
+                          quarantined (default {})",
+                         defaults.max_failures),
+                "N")
+        .optopt("c",
+                "checkpoint",
+                "File the tournament is saved to after every round \
+                 (default tournament.json in the working directory)",
+                "FILE")
+        .optopt("o",
+                "report",
+                "Directory the CSV and HTML report of the tournament is \
+                 written to",
+                "DIR")
+        .optflag("",
+                 "no-cache",
+                 "Play every game even if its outcome is cached")
+        .optopt("",
+                "engine",
+                &format!("The engine playing the games: external or \
+                          in-process (default {})",
+                         defaults.engine.name()),
+                "NAME")
+        .optflag("r",
+                 "resume",
+                 "Continue the tournament saved in the checkpoint after \
+                  its last complete round of games; the population options \
+                  are ignored and the others must be those it was begun \
+                  with");
+}
+
+fn tournament_config(matches: &Matches) -> Result<TournamentConfig, String>
+{
+    let defaults = tournament::Config::default();
+    if !matches.free.is_empty() {
+        return Err(format!("unexpected argument '{}'", matches.free[0]));
+    }
+    let n_players = match matches.opt_str("p") {
+        Some(range) => try!(parse_pair(&range, '-', "player range")),
+        None => defaults.n_players,
+    };
+    if n_players.0 < 2 || n_players.0 > n_players.1 {
+        return Err("bad player range".to_owned());
+    }
+    let map_sizes = try!(map_sizes_or(matches, &defaults.map_sizes));
+    let population_size = try!(opt_or(matches,
+                                       "n",
+                                       defaults.population_size,
+                                       "population size"));
+    if population_size < n_players.0 {
+        return Err("population is smaller than the games".to_owned());
+    }
+    let n_workers = try!(opt_or(matches,
+                                 "j",
+                                 defaults.n_workers,
+                                 "number of jobs"));
+    if n_workers < 1 {
+        return Err("bad number of jobs".to_owned());
+    }
+    let max_failures = try!(opt_or(matches,
+                                    "F",
+                                    defaults.max_failures,
+                                    "number of failures"));
+    if max_failures < 1 {
+        return Err("bad number of failures".to_owned());
+    }
+    let rating_systems = match matches.opt_str("R") {
+        Some(specs) => {
+            try!(specs.split(',')
+                      .map(rating::parse)
+                      .collect::<Result<Vec<_>, _>>())
+        }
+        None => defaults.rating_systems.clone(),
+    };
+    for (ix, system) in rating_systems.iter().enumerate() {
+        if rating_systems[..ix].iter().any(|s| s.name() == system.name()) {
+            return Err(format!("rating system '{}' given twice",
+                               system.name()));
+        }
+    }
+    let matchmaking = match matches.opt_str("matchmaking") {
+        Some(name) => try!(matchmaking::parse(&name)),
+        None => defaults.matchmaking.clone(),
+    };
+    let engine = match matches.opt_str("engine") {
+        Some(name) => try!(runner::parse_engine(&name)),
+        None => defaults.engine.clone(),
     };
-    let rater = bbt::Rater::default();
-    let old_ratings =
-        bots.iter().map(|bot| vec![bot.1.clone()]).collect::<Vec<_>>();
-    let new_ratings = rater.update_ratings(old_ratings,
-                                           outcome.rankings.clone())
-                           .unwrap();
-    for ix in 0..bots.len() {
-        bots[ix].1 = new_ratings[ix][0].clone();
-    }
-}
-
-fn randomize_bot<R: Rng>(bot: &mut Bot, rng: &mut R)
-{
-    let wt_range = Range::new(0_f32, 100.0);
-    let mm_range = Range::new(0_f32, 150.0);
-    let df_range = Range::new(0_f32, 1.0);
-    bot.set_param("aggression_weight", wt_range.ind_sample(rng))
-       .set_param("density_weight", wt_range.ind_sample(rng))
-       .set_param("expansion_weight", wt_range.ind_sample(rng))
-       .set_param("discount_factor", df_range.ind_sample(rng))
-       .set_param("minimum_movable_strength", mm_range.ind_sample(rng));
-}
-
-const POPULATION_SIZE: usize = 100;
-const TOURNAMENT_SIZE: usize = 1000;
-
-fn do_tournament(env: &Env, proto: &Bot)
-{
-    let mut bots = vec![(proto.clone(), Rating::default()); POPULATION_SIZE];
-    let mut rng = rand::thread_rng();
-    for bot in bots.iter_mut() {
-        randomize_bot(&mut bot.0, &mut rng);
-    }
-    // Run matches
-    let n_players_range = Range::new(2, 7);
-    let map_size_range = Range::new(5, 9);
-    for ix in 0..TOURNAMENT_SIZE {
-        bots.sort_by(|a, b| {
-            f32_cmp(&(b.1.sigma() as f32), &(a.1.sigma() as f32))
-        });
-        let max_sigma = bots[0].1.sigma();
-        let min_sigma = bots[bots.len() - 1].1.sigma();
-        let n_players = n_players_range.ind_sample(&mut rng);
-        let map_size = map_size_range.ind_sample(&mut rng) * 5;
-        println!("Game {}, Map = {}x{}, Players = {}, Sigma = ({:.*}, {:.*})",
-                 ix + 1,
-                 map_size,
-                 map_size,
-                 n_players,
-                 2,
-                 min_sigma,
-                 2,
-                 max_sigma);
-        let space = Space::with_dims(map_size, map_size);
-        do_match(env, &space, &mut bots[0..n_players]);
-    }
-    // Print results
-    bots.sort_by(|a, b| f32_cmp(&(b.1.mu() as f32), &(a.1.mu() as f32)));
-    for bot in bots {
-        println!("{:?}", bot);
+    if engine.is_in_process() && matches.opt_present("x") {
+        return Err("the in-process engine plays no bot executables"
+                       .to_owned());
     }
+    Ok(TournamentConfig {
+        bot_exe: matches.opt_str("x"),
+        brain: matches.opt_str("b"),
+        bot_spec: None,
+        resume: matches.opt_present("r"),
+        tournament: tournament::Config {
+            population_size: population_size,
+            n_games: try!(opt_or(matches,
+                                 "g",
+                                 defaults.n_games,
+                                 "number of games")),
+            n_players: n_players,
+            map_sizes: map_sizes,
+            n_workers: n_workers,
+            checkpoint: matches.opt_str("c").map(PathBuf::from),
+            seed: match matches.opt_str("s") {
+                Some(s) => Some(try!(parse_number(&s, "seed"))),
+                None => None,
+            },
+            max_failures: max_failures,
+            rating_systems: rating_systems,
+            matchmaking: matchmaking,
+            report: matches.opt_str("o").map(PathBuf::from),
+            cache: !matches.opt_present("no-cache"),
+            engine: engine,
+        },
+    })
+}
+
+fn parse_tournament(program: &str,
+                    args: &[String])
+    -> Result<OptionParsing<Command>, String>
+{
+    let mut opts = Options::new();
+    tournament_options(&mut opts, "");
+    opts.optopt("B",
+                "bots",
+                "Spec file of the bots making up the population; replaces \
+                 the executable, brain and population size",
+                "FILE");
+    let matches = try!(opts.parse(args).map_err(|e| e.to_string()));
+    if matches.opt_present("h") {
+        return Ok(OptionParsing::ShowUsage(command_usage(&opts,
+                                                         program,
+                                                         "tournament \
+                                                          [ options ]")));
+    }
+    let mut config = try!(tournament_config(&matches));
+    if let Some(path) = matches.opt_str("B") {
+        if ["x", "b", "n"].iter().any(|&o| matches.opt_present(o)) {
+            return Err("the bot spec file replaces the executable, brain \
+                        and population size"
+                           .to_owned());
+        }
+        config.bot_spec = Some(PathBuf::from(path));
+    }
+    Ok(OptionParsing::Config(Command::Tournament(config)))
+}
+
+fn parse_evolve(program: &str,
+                args: &[String])
+    -> Result<OptionParsing<Command>, String>
+{
+    let defaults = evolve::Config::default();
+    let mut opts = Options::new();
+    tournament_options(&mut opts, " per generation");
+    opts.optopt("G",
+                "generations",
+                &format!("Number of generations (default {})",
+                         defaults.n_generations),
+                "N")
+        .optopt("S",
+                "survival",
+                &format!("Fraction of bots surviving to the next \
+                          generation (default {})",
+                         defaults.survival),
+                "FRACTION")
+        .optopt("M",
+                "mutation",
+                &format!("Deviation of mutations relative to the parameter \
+                          ranges (default {})",
+                         defaults.mutation),
+                "FRACTION");
+    let matches = try!(opts.parse(args).map_err(|e| e.to_string()));
+    if matches.opt_present("h") {
+        return Ok(OptionParsing::ShowUsage(command_usage(&opts,
+                                                         program,
+                                                         "evolve [ options \
+                                                          ]")));
+    }
+    let tournament = try!(tournament_config(&matches));
+    let survival = try!(opt_or(&matches,
+                                "S",
+                                defaults.survival,
+                                "survival fraction"));
+    if !(0.0 < survival && survival <= 1.0) {
+        return Err("bad survival fraction".to_owned());
+    }
+    let mutation = try!(opt_or(&matches,
+                                "M",
+                                defaults.mutation,
+                                "mutation deviation"));
+    if !(mutation >= 0.0) {
+        return Err("bad mutation deviation".to_owned());
+    }
+    Ok(OptionParsing::Config(Command::Evolve(EvolveConfig {
+        tournament: tournament,
+        evolve: evolve::Config {
+            n_generations: try!(opt_or(&matches,
+                                       "G",
+                                       defaults.n_generations,
+                                       "number of generations")),
+            survival: survival,
+            mutation: mutation,
+        },
+    })))
+}
+
+fn parse_gauntlet(program: &str,
+                  args: &[String])
+    -> Result<OptionParsing<Command>, String>
+{
+    let defaults = gauntlet::Config::default();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this usage information")
+        .optopt("b", "brain", "The brain the candidate uses", "NAME")
+        .optopt("a",
+                "archive",
+                &format!("Directory of the archived releases (default {})",
+                         defaults.archive_dir.display()),
+                "DIR")
+        .optopt("g",
+                "games",
+                &format!("Number of games of each kind against each \
+                          release (default {})",
+                         defaults.n_games),
+                "N")
+        .optopt("s",
+                "seed",
+                &format!("Seed of the first map (default {})",
+                         defaults.base_seed),
+                "SEED")
+        .optopt("m",
+                "map-sizes",
+                "Side lengths of the square maps (default 25,30,35,40)",
+                "SIZE[,SIZE...]")
+        .optopt("f",
+                "ffa-players",
+                &format!("Number of players in free-for-all games (default \
+                          {})",
+                         defaults.n_ffa_players),
+                "N")
+        .optopt("j",
+                "jobs",
+                &format!("Number of games played at once (default {})",
+                         defaults.n_workers),
+                "N")
+        .optflag("",
+                 "no-cache",
+                 "Play every game even if its outcome is cached");
+    let matches = try!(opts.parse(args).map_err(|e| e.to_string()));
+    if matches.opt_present("h") {
+        return Ok(OptionParsing::ShowUsage(command_usage(&opts,
+                                                         program,
+                                                         "gauntlet [ options \
+                                                          ] [ CANDIDATE ]\n\n\
+                                                          Exits with status \
+                                                          2 if the candidate \
+                                                          is worse than the \
+                                                          newest release.")));
+    }
+    if matches.free.len() > 1 {
+        return Err(format!("unexpected argument '{}'", matches.free[1]));
+    }
+    let n_ffa_players = try!(opt_or(&matches,
+                                     "f",
+                                     defaults.n_ffa_players,
+                                     "number of free-for-all players"));
+    if n_ffa_players < 3 {
+        return Err("free-for-all games need at least three players"
+                       .to_owned());
+    }
+    let n_workers = try!(opt_or(&matches,
+                                 "j",
+                                 defaults.n_workers,
+                                 "number of jobs"));
+    if n_workers < 1 {
+        return Err("bad number of jobs".to_owned());
+    }
+    Ok(OptionParsing::Config(Command::Gauntlet(GauntletConfig {
+        candidate_exe: matches.free
+                              .get(0)
+                              .cloned()
+                              .unwrap_or(DEFAULT_BOT_EXE.to_owned()),
+        brain: matches.opt_str("b"),
+        gauntlet: gauntlet::Config {
+            archive_dir: matches.opt_str("a")
+                                .map(PathBuf::from)
+                                .unwrap_or(defaults.archive_dir.clone()),
+            n_games: try!(opt_or(&matches,
+                                 "g",
+                                 defaults.n_games,
+                                 "number of games")),
+            base_seed: try!(opt_or(&matches, "s", defaults.base_seed, "seed")),
+            map_sizes: try!(map_sizes_or(&matches, &defaults.map_sizes)),
+            n_ffa_players: n_ffa_players,
+            n_workers: n_workers,
+            cache: !matches.opt_present("no-cache"),
+        },
+    })))
+}
+
+fn parse_gc(program: &str,
+            args: &[String])
+    -> Result<OptionParsing<Command>, String>
+{
+    let defaults = gc::Config::default();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this usage information")
+        .optopt("c",
+                "checkpoint",
+                "File the tournament was saved to (default tournament.json \
+                 in the working directory)",
+                "FILE")
+        .optopt("R",
+                "rating",
+                "Rating system the bots are ranked by (default bbt)",
+                "SYSTEM")
+        .optopt("k",
+                "keep-top",
+                &format!("Number of the best bots whose replays are kept \
+                          (default {})",
+                         defaults.keep_top),
+                "N")
+        .optflag("", "no-upsets", "Do not keep the replays of upsets")
+        .optflag("n", "dry-run", "Only list the files that would be removed");
+    let matches = try!(opts.parse(args).map_err(|e| e.to_string()));
+    if matches.opt_present("h") {
+        return Ok(OptionParsing::ShowUsage(command_usage(&opts,
+                                                         program,
+                                                         "gc [ options ]")));
+    }
+    if !matches.free.is_empty() {
+        return Err(format!("unexpected argument '{}'", matches.free[0]));
+    }
+    let mut tournament = tournament::Config::default();
+    tournament.checkpoint = matches.opt_str("c").map(PathBuf::from);
+    if let Some(spec) = matches.opt_str("R") {
+        tournament.rating_systems = vec![try!(rating::parse(&spec))];
+    }
+    Ok(OptionParsing::Config(Command::Gc(GcConfig {
+        tournament: tournament,
+        gc: gc::Config {
+            keep_top: try!(opt_or(&matches,
+                                  "k",
+                                  defaults.keep_top,
+                                  "number of bots")),
+            keep_upsets: !matches.opt_present("no-upsets"),
+            dry_run: matches.opt_present("n"),
+        },
+    })))
+}
+
+fn parse_options() -> Result<OptionParsing<Config>, String>
+{
+    let mut opts = Options::new();
+    opts.parsing_style(ParsingStyle::StopAtFirstFree)
+        .optflag("h", "help", "Display this usage information")
+        .optopt("w",
+                "work-dir",
+                &format!("Directory for bot scripts and replays (default \
+                          {})",
+                         DEFAULT_WORK_DIR),
+                "DIR")
+        .optopt("H",
+                "halite",
+                &format!("The halite environment executable (default {})",
+                         DEFAULT_HALITE_EXE),
+                "FILE");
+    let args = env::args().collect::<Vec<String>>();
+    let matches = try!(opts.parse(&args[1..])
+                           .map_err(|_| {
+                               "bad command line (try -h for help)".to_owned()
+                           }));
+    if matches.opt_present("h") || matches.free.is_empty() {
+        let brief = format!("usage: {} [ options ] COMMAND [ command options \
+                             ]",
+                            args[0]);
+        return Ok(OptionParsing::ShowUsage(opts.usage(&brief) +
+                                           USAGE_COMMANDS));
+    }
+    let parsing = match matches.free[0].as_str() {
+        "match" => try!(parse_match(&args[0], &matches.free[1..])),
+        "tournament" => try!(parse_tournament(&args[0], &matches.free[1..])),
+        "evolve" => try!(parse_evolve(&args[0], &matches.free[1..])),
+        "gauntlet" => try!(parse_gauntlet(&args[0], &matches.free[1..])),
+        "gc" => try!(parse_gc(&args[0], &matches.free[1..])),
+        command => return Err(format!("no command with name '{}'", command)),
+    };
+    Ok(match parsing {
+        OptionParsing::Config(command) => {
+            OptionParsing::Config(Config {
+                work_dir: matches.opt_str("w")
+                                 .unwrap_or(DEFAULT_WORK_DIR.to_owned()),
+                halite_exe: matches.opt_str("H")
+                                   .unwrap_or(DEFAULT_HALITE_EXE.to_owned()),
+                command: command,
+            })
+        }
+        OptionParsing::ShowUsage(usage) => OptionParsing::ShowUsage(usage),
+    })
+}
+
+fn do_match(env: &Env, config: &MatchConfig) -> Result<(), runner::Error>
+{
+    let bots = try!(config.bots
+                          .iter()
+                          .map(Bot::new)
+                          .collect::<Result<Vec<_>, _>>());
+    let space = Space::with_dims(config.width, config.height);
+    let mut game = runner::Match::new(&space);
+    if let Some(seed) = config.seed {
+        game = game.seed(seed);
+    }
+    for bot in bots.iter() {
+        game = game.bot(bot);
+    }
+    let outcome = try!(game.run(env));
+    println!("Seed {}, replay {}",
+             outcome.seed,
+             outcome.hlt_path.display());
+    for (ix, bot) in bots.iter().enumerate() {
+        let stats = &outcome.stats[ix];
+        let last = stats.territory.len() - 1;
+        print!("{} {} (territory {}, strength {}, production {}",
+               outcome.rankings[ix],
+               bot,
+               stats.territory[last],
+               stats.strength[last],
+               stats.production[last]);
+        if let Some(turn) = stats.eliminated {
+            print!(", eliminated on turn {}", turn);
+        }
+        println!(")");
+    }
+    for failure in outcome.failures.iter() {
+        println!("{}", failure);
+    }
+    Ok(())
+}
+
+/// Loads the population from the checkpoint when resuming and otherwise
+/// creates a new one.  Every bot must be playable by the engine.
+fn init_population(env: &Env, config: &TournamentConfig)
+    -> Result<Checkpoint, runner::Error>
+{
+    let engine = &config.tournament.engine;
+    let state = if config.resume {
+        let path = config.tournament.checkpoint_path(env);
+        let state = try!(Checkpoint::load(&path));
+        if state.bots.len() < config.tournament.n_players.0 {
+            return Err(runner::Error::Format(format!("the population in {} \
+                                                      is smaller than the \
+                                                      games",
+                                                     path.display())));
+        }
+        if let Some(mismatch) = state.settings
+                                     .mismatch(&config.tournament.settings()) {
+            return Err(runner::Error::Format(format!("{} was begun with \
+                                                      other options: {}",
+                                                     path.display(),
+                                                     mismatch)));
+        }
+        println!("Resuming after game {} from {}",
+                 state.games.len(),
+                 path.display());
+        state
+    } else if let Some(ref path) = config.bot_spec {
+        let specs = try!(spec::load(path));
+        let state = try!(tournament::populate_from_specs(&specs,
+                                                         &config.tournament));
+        if state.bots.len() < config.tournament.n_players.0 {
+            return Err(runner::Error::Format(format!("{} has fewer bots \
+                                                      than the games",
+                                                     path.display())));
+        }
+        state
+    } else {
+        let brain = config.brain.as_ref().map(|b| b.as_str());
+        let proto = if engine.is_in_process() {
+            try!(Bot::hosted(brain.unwrap_or(ua::brain::default_name())))
+        } else {
+            let exe = config.bot_exe
+                            .as_ref()
+                            .map(|x| x.as_str())
+                            .unwrap_or(DEFAULT_BOT_EXE);
+            let mut proto = try!(Bot::new(exe));
+            if let Some(brain) = brain {
+                proto.brain(brain);
+            }
+            proto
+        };
+        let schema = try!(proto.schema());
+        tournament::populate(&proto, &schema, &config.tournament)
+    };
+    for entry in state.bots.iter() {
+        try!(engine.check(&entry.bot));
+    }
+    Ok(state)
+}
+
+fn do_tournament(env: &Env, config: &TournamentConfig)
+    -> Result<(), runner::Error>
+{
+    let mut state = try!(init_population(env, config));
+    tournament::run(env, &mut state, &config.tournament)
+}
+
+fn do_evolve(env: &Env, config: &EvolveConfig) -> Result<(), runner::Error>
+{
+    let mut state = try!(init_population(env, &config.tournament));
+    let schema = try!(state.bots[0].bot.schema());
+    evolve::run(env,
+                &mut state,
+                &schema,
+                &config.tournament.tournament,
+                &config.evolve)
+}
+
+fn do_gauntlet(env: &Env, config: &GauntletConfig)
+    -> Result<(), runner::Error>
+{
+    let mut candidate = try!(Bot::new(&config.candidate_exe));
+    if let Some(ref brain) = config.brain {
+        candidate.brain(brain);
+    }
+    if !try!(gauntlet::run(env, &candidate, &config.gauntlet)) {
+        println!("The candidate is worse than the newest release");
+        process::exit(2);
+    }
+    Ok(())
+}
+
+fn do_gc(env: &Env, config: &GcConfig) -> Result<(), runner::Error>
+{
+    let state = try!(Checkpoint::load(config.tournament.checkpoint_path(env)));
+    gc::run(env, &state, &config.tournament, &config.gc)
 }
 
 fn main()
 {
-    // Next up generate 100 different bots and
-    match runner::Env::new("./tmp/runner", "../Environment/halite") {
-        Ok(env) => {
-            let proto = runner::Bot::new("./target/release/MyBot").unwrap();
-            do_tournament(&env, &proto);
+    let config = match parse_options() {
+        Ok(OptionParsing::Config(config)) => config,
+        Ok(OptionParsing::ShowUsage(usage)) => {
+            println!("{}", usage);
+            process::exit(0);
+        }
+        Err(why) => {
+            writeln!(std::io::stderr(), "Error: {}", why).unwrap();
+            process::exit(1);
         }
+    };
+    let env = match Env::new(&config.work_dir) {
+        Ok(env) => env.with_halite(&config.halite_exe),
         Err(e) => {
-            println!("Failed to initialize environment: {:?}", e);
+            writeln!(std::io::stderr(),
+                     "Failed to initialize environment: {}",
+                     e)
+                .unwrap();
             process::exit(1);
         }
     };
+    let result = match config.command {
+        Command::Match(ref config) => do_match(&env, config),
+        Command::Tournament(ref config) => do_tournament(&env, config),
+        Command::Evolve(ref config) => do_evolve(&env, config),
+        Command::Gauntlet(ref config) => do_gauntlet(&env, config),
+        Command::Gc(ref config) => do_gc(&env, config),
+    };
+    if let Err(e) = result {
+        writeln!(std::io::stderr(), "Error: {}", e).unwrap();
+        process::exit(1);
+    }
 }
@@ -0,0 +1,438 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Policies for picking the players of the tournament games.
+//!
+//! The policies trade the speed at which the ratings converge against the
+//! fairness of the schedule.  Picking the most uncertain bots converges
+//! fast but keeps grouping the same bots together, picking at random is
+//! fair but slow, Swiss pairing gives close games and round-robin plays
+//! every combination of bots in turn.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use rand::{Rng, StdRng};
+use rand::distributions::{IndependentSample, Range};
+
+use ua::util::f32_cmp;
+
+use checkpoint::Checkpoint;
+use tournament::Config;
+
+pub trait Matchmaking: fmt::Debug
+{
+    fn name(&self) -> &str;
+
+    /// Picks the players of at most `n_games` games to be played at the
+    /// same time from the indices of the bots in `candidates`.  At least
+    /// one game is picked as long as there are enough candidates.
+    fn pick(&self,
+            state: &Checkpoint,
+            config: &Config,
+            candidates: &[usize],
+            n_games: usize,
+            rng: &mut StdRng)
+        -> Vec<Vec<usize>>;
+}
+
+pub type Policy = Rc<Matchmaking>;
+
+/// Draws the number of players for as many games as there are players for
+/// but at most `n_games`.
+fn draw_sizes(config: &Config,
+              n_candidates: usize,
+              n_games: usize,
+              rng: &mut StdRng)
+    -> Vec<usize>
+{
+    let max_players = config.n_players.1.min(n_candidates);
+    let range = Range::new(config.n_players.0, max_players + 1);
+    let mut sizes = Vec::new();
+    let mut n_taken = 0;
+    while sizes.len() < n_games {
+        let size = range.ind_sample(rng);
+        if n_taken + size > n_candidates {
+            break;
+        }
+        sizes.push(size);
+        n_taken += size;
+    }
+    sizes
+}
+
+/// Cuts `order` into consecutive groups of the given sizes.
+fn cut(order: &[usize], sizes: &[usize]) -> Vec<Vec<usize>>
+{
+    let mut start = 0;
+    sizes.iter()
+         .map(|&size| {
+             start += size;
+             order[start - size..start].to_vec()
+         })
+         .collect()
+}
+
+/// Groups the bots with the most uncertain ratings together.  Bots with
+/// equal uncertainty, as under Elo where there is none, are grouped at
+/// random.
+#[derive(Clone, Debug)]
+pub struct Sigma;
+
+impl Matchmaking for Sigma
+{
+    fn name(&self) -> &str
+    {
+        "sigma"
+    }
+
+    fn pick(&self,
+            state: &Checkpoint,
+            config: &Config,
+            candidates: &[usize],
+            n_games: usize,
+            rng: &mut StdRng)
+        -> Vec<Vec<usize>>
+    {
+        let system = config.primary_rating();
+        let mut order = candidates.to_vec();
+        // Shuffle first so the stable sort breaks the ties at random
+        rng.shuffle(&mut order);
+        order.sort_by(|&a, &b| {
+            f32_cmp(&(state.bots[b].skill(system).sigma as f32),
+                    &(state.bots[a].skill(system).sigma as f32))
+        });
+        cut(&order, &draw_sizes(config, order.len(), n_games, rng))
+    }
+}
+
+/// Groups the bots at random.
+#[derive(Clone, Debug)]
+pub struct Uniform;
+
+impl Matchmaking for Uniform
+{
+    fn name(&self) -> &str
+    {
+        "random"
+    }
+
+    fn pick(&self,
+            _state: &Checkpoint,
+            config: &Config,
+            candidates: &[usize],
+            n_games: usize,
+            rng: &mut StdRng)
+        -> Vec<Vec<usize>>
+    {
+        let mut order = candidates.to_vec();
+        rng.shuffle(&mut order);
+        cut(&order, &draw_sizes(config, order.len(), n_games, rng))
+    }
+}
+
+/// Groups a bot picked at random with the bots closest to it in `mu`.
+#[derive(Clone, Debug)]
+pub struct Swiss;
+
+impl Matchmaking for Swiss
+{
+    fn name(&self) -> &str
+    {
+        "swiss"
+    }
+
+    fn pick(&self,
+            state: &Checkpoint,
+            config: &Config,
+            candidates: &[usize],
+            n_games: usize,
+            rng: &mut StdRng)
+        -> Vec<Vec<usize>>
+    {
+        let system = config.primary_rating();
+        let mu = |ix: usize| state.bots[ix].skill(system).mu;
+        let mut free = candidates.to_vec();
+        rng.shuffle(&mut free);
+        let sizes = draw_sizes(config, free.len(), n_games, rng);
+        let mut groups = Vec::new();
+        for size in sizes {
+            let seed = free.remove(0);
+            free.sort_by(|&a, &b| {
+                f32_cmp(&((mu(a) - mu(seed)).abs() as f32),
+                        &((mu(b) - mu(seed)).abs() as f32))
+            });
+            let mut group = vec![seed];
+            group.extend(free.drain(..size - 1));
+            groups.push(group);
+            // Restore the random order of the seeds
+            rng.shuffle(&mut free);
+        }
+        groups
+    }
+}
+
+/// Plays every combination of bots of every allowed size in turn.  The
+/// number of combinations grows fast so this is for small populations.
+///
+/// The combinations played the fewest times so far go first and the games
+/// picked at the same time have no bots in common.  The combinations are of
+/// the bots themselves so quarantining a bot only leaves out those it is
+/// in.
+#[derive(Clone, Debug)]
+pub struct RoundRobin;
+
+fn n_combinations(n: usize, k: usize) -> usize
+{
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+/// The `index`th `k`-combination of `0..n` in lexicographic order.
+fn nth_combination(n: usize, k: usize, mut index: usize) -> Vec<usize>
+{
+    let mut combination = Vec::with_capacity(k);
+    let mut next = 0;
+    for slot in 0..k {
+        loop {
+            let rest = n_combinations(n - next - 1, k - slot - 1);
+            if index < rest {
+                break;
+            }
+            index -= rest;
+            next += 1;
+        }
+        combination.push(next);
+        next += 1;
+    }
+    combination
+}
+
+impl Matchmaking for RoundRobin
+{
+    fn name(&self) -> &str
+    {
+        "round-robin"
+    }
+
+    fn pick(&self,
+            state: &Checkpoint,
+            config: &Config,
+            candidates: &[usize],
+            n_games: usize,
+            _rng: &mut StdRng)
+        -> Vec<Vec<usize>>
+    {
+        let mut n_played = HashMap::new();
+        for game in state.games.iter() {
+            let mut players = game.players.clone();
+            players.sort();
+            *n_played.entry(players).or_insert(0) += 1;
+        }
+        let n = candidates.len();
+        let mut schedule = Vec::new();
+        for k in config.n_players.0..config.n_players.1 + 1 {
+            for ix in 0..n_combinations(n, k) {
+                let group = nth_combination(n, k, ix)
+                                .into_iter()
+                                .map(|c| candidates[c])
+                                .collect::<Vec<_>>();
+                let count = n_played.get(&group).cloned().unwrap_or(0);
+                schedule.push((count, group));
+            }
+        }
+        // The sort is stable so the combinations played equally often stay
+        // in the order of the schedule
+        schedule.sort_by_key(|&(count, _)| count);
+        let mut taken = HashSet::new();
+        let mut groups = Vec::new();
+        for (_, group) in schedule {
+            if groups.len() == n_games {
+                break;
+            }
+            if group.iter().any(|ix| taken.contains(ix)) {
+                continue;
+            }
+            taken.extend(group.iter().cloned());
+            groups.push(group);
+        }
+        groups
+    }
+}
+
+/// Parses the name of a matchmaking policy.
+pub fn parse(name: &str) -> Result<Policy, String>
+{
+    match name {
+        "sigma" => Ok(Rc::new(Sigma)),
+        "random" => Ok(Rc::new(Uniform)),
+        "swiss" => Ok(Rc::new(Swiss)),
+        "round-robin" => Ok(Rc::new(RoundRobin)),
+        _ => Err(format!("no matchmaking policy with name '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rand::{SeedableRng, StdRng};
+
+    use checkpoint::Checkpoint;
+    use checkpoint::fixture::{game, population};
+    use tournament::Config;
+    use super::{Matchmaking, RoundRobin, Sigma, Swiss, nth_combination};
+
+    #[test]
+    fn test_nth_combination()
+    {
+        assert_eq!(nth_combination(4, 2, 0), vec![0, 1]);
+        assert_eq!(nth_combination(4, 2, 2), vec![0, 3]);
+        assert_eq!(nth_combination(4, 2, 3), vec![1, 2]);
+        assert_eq!(nth_combination(4, 2, 5), vec![2, 3]);
+        assert_eq!(nth_combination(5, 3, 9), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_pairs()
+    {
+        let mut state = population(&[0.0, 0.0, 0.0]);
+        let mut config = Config::default();
+        config.n_players = (2, 2);
+        let seed: &[_] = &[1];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let candidates = [0, 1, 2];
+        let mut played = Vec::new();
+        for _ in 0..3 {
+            let groups = RoundRobin.pick(&state,
+                                         &config,
+                                         &candidates,
+                                         4,
+                                         &mut rng);
+            assert_eq!(groups.len(), 1);
+            state.games.push(game(groups[0].clone()));
+            played.extend(groups);
+        }
+        assert_eq!(played, vec![vec![0, 1], vec![0, 2], vec![1, 2]]);
+    }
+
+    /// Plays `n_rounds` rounds of round-robin of at most `n_games` games
+    /// and returns the rounds.
+    fn round_robin(state: &mut Checkpoint,
+                   n_players: (usize, usize),
+                   candidates: &[usize],
+                   n_rounds: usize,
+                   n_games: usize)
+        -> Vec<Vec<Vec<usize>>>
+    {
+        let mut config = Config::default();
+        config.n_players = n_players;
+        let seed: &[_] = &[1];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        (0..n_rounds)
+            .map(|_| {
+                let groups = RoundRobin.pick(state,
+                                             &config,
+                                             candidates,
+                                             n_games,
+                                             &mut rng);
+                for group in groups.iter() {
+                    state.games.push(game(group.clone()));
+                }
+                groups
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_fills_rounds_with_disjoint_games()
+    {
+        let mut state = population(&[0.0, 0.0, 0.0, 0.0]);
+        let rounds = round_robin(&mut state, (2, 2), &[0, 1, 2, 3], 3, 4);
+        assert_eq!(rounds,
+                   vec![vec![vec![0, 1], vec![2, 3]],
+                        vec![vec![0, 2], vec![1, 3]],
+                        vec![vec![0, 3], vec![1, 2]]]);
+    }
+
+    #[test]
+    fn test_round_robin_plays_every_size()
+    {
+        let mut state = population(&[0.0, 0.0, 0.0]);
+        let rounds = round_robin(&mut state, (2, 3), &[0, 1, 2], 4, 2);
+        assert_eq!(rounds,
+                   vec![vec![vec![0, 1]],
+                        vec![vec![0, 2]],
+                        vec![vec![1, 2]],
+                        vec![vec![0, 1, 2]]]);
+    }
+
+    #[test]
+    fn test_round_robin_skips_quarantined_bots()
+    {
+        let mut state = population(&[0.0, 0.0, 0.0, 0.0]);
+        state.games.push(game(vec![0, 2]));
+        // The bot 1 has been quarantined
+        let rounds = round_robin(&mut state, (2, 2), &[0, 2, 3], 2, 1);
+        assert_eq!(rounds, vec![vec![vec![0, 3]], vec![vec![2, 3]]]);
+    }
+
+    #[test]
+    fn test_sigma_breaks_ties_at_random()
+    {
+        let state = population(&[0.0, 0.0, 0.0, 0.0]);
+        let mut config = Config::default();
+        config.n_players = (2, 2);
+        let mut firsts = Vec::new();
+        for s in 0..8 {
+            let seed: &[_] = &[s];
+            let mut rng: StdRng = SeedableRng::from_seed(seed);
+            let mut groups = Sigma.pick(&state,
+                                        &config,
+                                        &[0, 1, 2, 3],
+                                        1,
+                                        &mut rng);
+            groups[0].sort();
+            firsts.push(groups.remove(0));
+        }
+        firsts.sort();
+        firsts.dedup();
+        assert!(firsts.len() > 1);
+    }
+
+    #[test]
+    fn test_swiss_groups_close_ratings()
+    {
+        let state = population(&[10.0, 30.0, 11.0, 31.0, 12.0, 32.0]);
+        let mut config = Config::default();
+        config.n_players = (3, 3);
+        let seed: &[_] = &[2];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut groups = Swiss.pick(&state,
+                                    &config,
+                                    &[0, 1, 2, 3, 4, 5],
+                                    2,
+                                    &mut rng);
+        for group in groups.iter_mut() {
+            group.sort();
+        }
+        groups.sort();
+        assert_eq!(groups, vec![vec![0, 2, 4], vec![1, 3, 5]]);
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A pool of workers playing matches side by side.
+//!
+//! Each worker has a working directory of its own under the working
+//! directory of the environment so that the bot scripts and the replays of
+//! the concurrent matches do not step on each other.  With a cache the pool
+//! plays only the matches whose outcome is not in the cache already.
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use ua::brain::panic_message;
+use ua::space::Space;
+
+use cache::Cache;
+use runner::{self, Bot, Engine, Env, Error, Outcome};
+
+/// A match to be played by the pool.
+#[derive(Clone, Debug)]
+pub struct Job
+{
+    pub width: i16,
+    pub height: i16,
+    pub seed: Option<usize>,
+    pub bots: Vec<Bot>,
+    /// The engine the match is played with
+    pub engine: Arc<Engine>,
+}
+
+impl Job
+{
+    fn play(&self, env: &Env) -> Result<Outcome, Error>
+    {
+        let space = Space::with_dims(self.width, self.height);
+        let mut game = runner::Match::new(&space);
+        if let Some(seed) = self.seed {
+            game = game.seed(seed);
+        }
+        for bot in self.bots.iter() {
+            game = game.bot(bot);
+        }
+        game.run_with(&*self.engine, env)
+    }
+
+    /// Plays the match turning a panic into an error so that the pool
+    /// gets a result for every job.
+    fn run(&self, env: &Env) -> Result<Outcome, Error>
+    {
+        panic::catch_unwind(AssertUnwindSafe(|| self.play(env)))
+            .unwrap_or_else(|payload| {
+                Err(Error::Runtime(format!("worker panicked: {}",
+                                           panic_message(&payload))))
+            })
+    }
+}
+
+type Result_ = (usize, Result<Outcome, Error>);
+
+pub struct Pool
+{
+    jobs: Option<Sender<(usize, Job)>>,
+    results: Receiver<Result_>,
+    workers: Vec<JoinHandle<()>>,
+    cache: Option<Cache>,
+}
+
+impl Pool
+{
+    /// Starts `n_workers` workers playing in the environment `env`.
+    pub fn new(env: &Env, n_workers: usize) -> Result<Self, Error>
+    {
+        assert!(n_workers > 0);
+        let (job_tx, job_rx) = mpsc::channel::<(usize, Job)>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let mut workers = Vec::with_capacity(n_workers);
+        for ix in 0..n_workers {
+            let work_dir = env.work_dir().join(format!("worker-{}", ix + 1));
+            try!(fs::create_dir_all(&work_dir));
+            let env = try!(env.in_dir(&work_dir));
+            let job_rx = job_rx.clone();
+            let result_tx: Sender<Result_> = result_tx.clone();
+            workers.push(thread::spawn(move || {
+                loop {
+                    // The lock is released before the job is run.
+                    let next = job_rx.lock().unwrap().recv();
+                    match next {
+                        Ok((id, job)) => {
+                            if result_tx.send((id, job.run(&env))).is_err() {
+                                return;
+                            }
+                        }
+                        Err(_) => return,
+                    }
+                }
+            }));
+        }
+        Ok(Pool {
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers: workers,
+            cache: None,
+        })
+    }
+
+    /// Makes the pool look up the outcomes in `cache` before playing the
+    /// matches and store the outcomes of the matches it plays.
+    pub fn with_cache(mut self, cache: Cache) -> Self
+    {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Plays the matches and returns their outcomes in the order of `jobs`
+    /// no matter in which order they finish.
+    pub fn run_all(&self, jobs: &[Job]) -> Vec<Result<Outcome, Error>>
+    {
+        let sender = self.jobs.as_ref().unwrap();
+        let mut results = jobs.iter()
+                              .map(|job| {
+                                  self.cache
+                                      .as_ref()
+                                      .and_then(|c| c.get(job))
+                                      .map(Ok)
+                              })
+                              .collect::<Vec<_>>();
+        let mut n_played = 0;
+        for (id, job) in jobs.iter().enumerate() {
+            if results[id].is_none() {
+                sender.send((id, job.clone())).unwrap();
+                n_played += 1;
+            }
+        }
+        for _ in 0..n_played {
+            let (id, result) = self.results
+                                   .recv()
+                                   .expect("worker died in the middle of a \
+                                            match");
+            if let (Some(cache), &Ok(ref outcome)) = (self.cache.as_ref(),
+                                                      &result) {
+                if let Err(e) = cache.put(&jobs[id], outcome) {
+                    println!("Could not cache the outcome: {}", e);
+                }
+            }
+            results[id] = Some(result);
+        }
+        results.into_iter().map(|r| r.unwrap()).collect()
+    }
+}
+
+impl Drop for Pool
+{
+    fn drop(&mut self)
+    {
+        // Hanging up the job queue tells the workers to quit.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+
+    use checkpoint::fixture::{self, TempDir};
+    use runner::{Engine, Env, Error, Match, Outcome};
+    use super::{Job, Pool};
+
+    #[derive(Debug)]
+    struct Panicking;
+
+    impl Engine for Panicking
+    {
+        fn name(&self) -> &str
+        {
+            "panicking"
+        }
+
+        fn play(&self, _: &Match, _: &Env) -> Result<Outcome, Error>
+        {
+            panic!("out of luck")
+        }
+    }
+
+    #[test]
+    fn test_survives_panicking_jobs()
+    {
+        let dir = TempDir::new("pool");
+        let pool = Pool::new(&Env::new(dir.path()).unwrap(), 2).unwrap();
+        let bot = fixture::bot();
+        let job = Job {
+            width: 10,
+            height: 10,
+            seed: None,
+            bots: vec![bot.clone(), bot],
+            engine: Arc::new(Panicking),
+        };
+        let results = pool.run_all(&[job.clone(), job.clone(), job]);
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.is_err()));
+    }
+}
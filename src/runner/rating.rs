@@ -0,0 +1,393 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rating systems for ranking the bots from the outcomes of their games.
+//!
+//! A system is given on the command line as its name optionally followed by
+//! its parameter, for example `bbt`, `elo:k=16` or `glicko2:tau=0.3`.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::rc::Rc;
+
+use bbt;
+
+/// The skill of a player as estimated by a rating system.  The systems that
+/// do not track the uncertainty of the estimate leave `sigma` zero and only
+/// Glicko-2 uses `volatility`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skill
+{
+    pub mu: f64,
+    pub sigma: f64,
+    pub volatility: f64,
+}
+
+pub trait RatingSystem: fmt::Debug
+{
+    /// The name the ratings of this system are saved under.
+    fn name(&self) -> &str;
+
+    /// The system with its parameter as `parse` takes it.
+    fn spec(&self) -> String;
+
+    /// The skill of a player that has not played yet.
+    fn initial(&self) -> Skill;
+
+    /// The skills of the players after a game given their skills before the
+    /// game and their ranks in it, the lower rank the better.
+    fn update(&self, skills: &[Skill], ranks: &[usize]) -> Vec<Skill>;
+}
+
+pub type System = Rc<RatingSystem>;
+
+/// The Bayesian approximation of Weng and Lin (2011) that the `bbt` crate
+/// implements.
+#[derive(Clone, Debug)]
+pub struct Bbt
+{
+    pub beta: f64,
+}
+
+impl RatingSystem for Bbt
+{
+    fn name(&self) -> &str
+    {
+        "bbt"
+    }
+
+    fn spec(&self) -> String
+    {
+        format!("bbt:beta={}", self.beta)
+    }
+
+    fn initial(&self) -> Skill
+    {
+        let rating = bbt::Rating::default();
+        Skill {
+            mu: rating.mu(),
+            sigma: rating.sigma(),
+            volatility: 0.0,
+        }
+    }
+
+    fn update(&self, skills: &[Skill], ranks: &[usize]) -> Vec<Skill>
+    {
+        let rater = bbt::Rater::new(self.beta);
+        let teams = skills.iter()
+                          .map(|s| vec![bbt::Rating::new(s.mu, s.sigma)])
+                          .collect();
+        rater.update_ratings(teams, ranks.to_vec())
+             .unwrap()
+             .into_iter()
+             .map(|team| {
+                 Skill {
+                     mu: team[0].mu(),
+                     sigma: team[0].sigma(),
+                     volatility: 0.0,
+                 }
+             })
+             .collect()
+    }
+}
+
+/// Elo generalised to more than two players by treating a game as a duel
+/// between every pair of players.  The `k` factor is shared among the
+/// duels of a player so that a game moves a rating as much as one duel
+/// would in two-player Elo.
+#[derive(Clone, Debug)]
+pub struct Elo
+{
+    pub k: f64,
+}
+
+/// The score of the player with the rank `a` against the one with `b`.
+fn duel_score(a: usize, b: usize) -> f64
+{
+    if a < b {
+        1.0
+    } else if a == b {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+impl RatingSystem for Elo
+{
+    fn name(&self) -> &str
+    {
+        "elo"
+    }
+
+    fn spec(&self) -> String
+    {
+        format!("elo:k={}", self.k)
+    }
+
+    fn initial(&self) -> Skill
+    {
+        Skill {
+            mu: 1500.0,
+            sigma: 0.0,
+            volatility: 0.0,
+        }
+    }
+
+    fn update(&self, skills: &[Skill], ranks: &[usize]) -> Vec<Skill>
+    {
+        let n = skills.len();
+        (0..n)
+            .map(|i| {
+                let mut delta = 0.0;
+                for j in (0..n).filter(|&j| j != i) {
+                    let expected =
+                        1.0 /
+                        (1.0 + 10f64.powf((skills[j].mu - skills[i].mu) /
+                                          400.0));
+                    delta += duel_score(ranks[i], ranks[j]) - expected;
+                }
+                Skill {
+                    mu: skills[i].mu + self.k * delta / (n - 1) as f64,
+                    ..skills[i].clone()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Glickman's Glicko-2 with a game as a rating period in which the player
+/// met each of the other players once.
+#[derive(Clone, Debug)]
+pub struct Glicko2
+{
+    /// The constraint on the change of volatility
+    pub tau: f64,
+}
+
+const GLICKO2_SCALE: f64 = 173.7178;
+const GLICKO2_EPSILON: f64 = 0.000001;
+
+fn glicko2_g(phi: f64) -> f64
+{
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+impl Glicko2
+{
+    /// The new volatility by the Illinois algorithm of step 5 in Glickman's
+    /// description of the system.
+    fn volatility(&self, phi: f64, sigma: f64, v: f64, delta: f64) -> f64
+    {
+        let tau2 = self.tau * self.tau;
+        let a = (sigma * sigma).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            let d = phi * phi + v + ex;
+            ex * (delta * delta - d) / (2.0 * d * d) - (x - a) / tau2
+        };
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * self.tau) < 0.0 {
+                k += 1.0;
+            }
+            a - k * self.tau
+        };
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+        while (big_b - big_a).abs() > GLICKO2_EPSILON {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b < 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = big_c;
+            f_b = f_c;
+        }
+        (big_a / 2.0).exp()
+    }
+}
+
+impl RatingSystem for Glicko2
+{
+    fn name(&self) -> &str
+    {
+        "glicko2"
+    }
+
+    fn spec(&self) -> String
+    {
+        format!("glicko2:tau={}", self.tau)
+    }
+
+    fn initial(&self) -> Skill
+    {
+        Skill {
+            mu: 1500.0,
+            sigma: 350.0,
+            volatility: 0.06,
+        }
+    }
+
+    fn update(&self, skills: &[Skill], ranks: &[usize]) -> Vec<Skill>
+    {
+        let scaled = skills.iter()
+                           .map(|s| {
+                               ((s.mu - 1500.0) / GLICKO2_SCALE,
+                                s.sigma / GLICKO2_SCALE)
+                           })
+                           .collect::<Vec<_>>();
+        (0..skills.len())
+            .map(|i| {
+                let (mu, phi) = scaled[i];
+                let mut v_inv = 0.0;
+                let mut sum = 0.0;
+                for (j, &(mu_j, phi_j)) in scaled.iter().enumerate() {
+                    if j == i {
+                        continue;
+                    }
+                    let g = glicko2_g(phi_j);
+                    let e = 1.0 / (1.0 + (-g * (mu - mu_j)).exp());
+                    v_inv += g * g * e * (1.0 - e);
+                    sum += g * (duel_score(ranks[i], ranks[j]) - e);
+                }
+                let v = 1.0 / v_inv;
+                let volatility =
+                    self.volatility(phi, skills[i].volatility, v, v * sum);
+                let phi_star = (phi * phi + volatility * volatility).sqrt();
+                let phi = 1.0 / (1.0 / (phi_star * phi_star) + v_inv).sqrt();
+                Skill {
+                    mu: 1500.0 + GLICKO2_SCALE * (mu + phi * phi * sum),
+                    sigma: GLICKO2_SCALE * phi,
+                    volatility: volatility,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parses a rating system given as `NAME` or `NAME:PARAM=VALUE`.
+pub fn parse(spec: &str) -> Result<System, String>
+{
+    let mut parts = spec.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    let param = match parts.next() {
+        Some(param) => {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim().to_owned();
+            let value = try!(kv.next()
+                               .and_then(|v| v.trim().parse::<f64>().ok())
+                               .ok_or_else(|| {
+                                   format!("bad rating parameter '{}'", param)
+                               }));
+            Some((key, value))
+        }
+        None => None,
+    };
+    let check = |expected: &str, default: f64| {
+        match param {
+            Some((ref key, value)) if key == expected && value > 0.0 => {
+                Ok(value)
+            }
+            Some(_) => {
+                Err(format!("rating system '{}' takes only '{}'",
+                            name,
+                            expected))
+            }
+            None => Ok(default),
+        }
+    };
+    match name {
+        "bbt" => Ok(Rc::new(Bbt { beta: try!(check("beta", 25.0 / 6.0)) })),
+        "elo" => Ok(Rc::new(Elo { k: try!(check("k", 32.0)) })),
+        "glicko2" => {
+            Ok(Rc::new(Glicko2 { tau: try!(check("tau", 0.5)) }))
+        }
+        _ => Err(format!("no rating system with name '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{Elo, Glicko2, RatingSystem, Skill, parse};
+
+    fn close(a: f64, b: f64, eps: f64) -> bool
+    {
+        (a - b).abs() < eps
+    }
+
+    #[test]
+    fn test_elo_duel()
+    {
+        let elo = Elo { k: 32.0 };
+        let skills = vec![elo.initial(), elo.initial()];
+        let updated = elo.update(&skills, &[1, 2]);
+        assert_eq!(updated[0].mu, 1516.0);
+        assert_eq!(updated[1].mu, 1484.0);
+        let updated = elo.update(&skills, &[1, 1]);
+        assert_eq!(updated[0].mu, 1500.0);
+    }
+
+    #[test]
+    fn test_glicko2_matches_glickman_example()
+    {
+        // The example in Glickman's description of Glicko-2 has the player
+        // meet three opponents in a period; as a three-player game against
+        // them all would also pit the opponents against each other only the
+        // first player's result is checked.
+        let glicko = Glicko2 { tau: 0.5 };
+        let skill = |mu, sigma| {
+            Skill {
+                mu: mu,
+                sigma: sigma,
+                volatility: 0.06,
+            }
+        };
+        let skills = vec![skill(1500.0, 200.0),
+                          skill(1400.0, 30.0),
+                          skill(1550.0, 100.0),
+                          skill(1700.0, 300.0)];
+        // Beat the first opponent, lose to the others
+        let updated = glicko.update(&skills, &[2, 3, 1, 1]);
+        assert!(close(updated[0].mu, 1464.06, 0.01));
+        assert!(close(updated[0].sigma, 151.52, 0.01));
+        assert!(close(updated[0].volatility, 0.05999, 0.00001));
+    }
+
+    #[test]
+    fn test_parse()
+    {
+        assert_eq!(parse("bbt").unwrap().name(), "bbt");
+        assert_eq!(parse("elo:k=16").unwrap().name(), "elo");
+        assert_eq!(parse("glicko2:tau=0.3").unwrap().name(), "glicko2");
+        assert!(parse("elo:tau=0.3").is_err());
+        assert!(parse("elo:k=x").is_err());
+        assert!(parse("trueskill").is_err());
+        for spec in ["bbt", "elo:k=16", "glicko2:tau=0.3"].iter() {
+            let spec = parse(spec).unwrap().spec();
+            assert_eq!(parse(&spec).unwrap().spec(), spec);
+        }
+        assert_eq!(parse("elo:k=16").unwrap().spec(), "elo:k=16");
+    }
+}
@@ -0,0 +1,512 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reports of the outcome of a tournament.
+//!
+//! The report is a directory with the leaderboard, the head-to-head record
+//! of every pair of bots, the ratings after every game and the list of the
+//! games as CSV files for spreadsheets plus `report.html` showing the same
+//! for browsing.  The rating history is recomputed from the games of the
+//! current generation starting from the ratings the bots had when it
+//! began.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use checkpoint::{Checkpoint, Entry};
+use rating::Skill;
+use runner::Error;
+use runner::util::route_between;
+use tournament::{self, Config};
+
+/// The number of the best bots whose ratings are plotted.
+const N_PLOTTED: usize = 10;
+
+const PLOT_WIDTH: f64 = 800.0;
+const PLOT_HEIGHT: f64 = 300.0;
+const PLOT_COLORS: [&'static str; N_PLOTTED] = ["#1f77b4", "#ff7f0e",
+                                                "#2ca02c", "#d62728",
+                                                "#9467bd", "#8c564b",
+                                                "#e377c2", "#7f7f7f",
+                                                "#bcbd22", "#17becf"];
+
+/// `wins[a][b]` is the number of games in which the bot `a` ranked above
+/// the bot `b`.  The players that failed are left out as in the ratings.
+fn head_to_head(state: &Checkpoint) -> Vec<Vec<usize>>
+{
+    let n_bots = state.bots.len();
+    let mut wins = vec![vec![0; n_bots]; n_bots];
+    for game in state.games.iter() {
+        let seats = game.players
+                        .iter()
+                        .zip(game.rankings.iter())
+                        .filter(|&(ix, _)| !game.failed.contains(ix))
+                        .collect::<Vec<_>>();
+        for &(&a, &rank_a) in seats.iter() {
+            for &(&b, &rank_b) in seats.iter() {
+                if rank_a < rank_b {
+                    wins[a][b] += 1;
+                }
+            }
+        }
+    }
+    wins
+}
+
+/// The skills of the bots by the primary rating system after each game.
+fn rating_history(state: &Checkpoint, config: &Config) -> Vec<Vec<Skill>>
+{
+    let mut bots = state.bots
+                        .iter()
+                        .map(|e| {
+                            let mut entry = Entry::new(e.bot.clone());
+                            entry.ratings = e.initial_ratings.clone();
+                            entry
+                        })
+                        .collect::<Vec<_>>();
+    let system = config.rating_systems[0].clone();
+    let systems = [system.clone()];
+    state.games
+         .iter()
+         .map(|game| {
+             tournament::update_ratings(&mut bots, game, &systems);
+             bots.iter().map(|e| e.skill(&*system)).collect()
+         })
+         .collect()
+}
+
+fn csv_field(field: &str) -> String
+{
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace("\"", "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn write_csv_row<W: Write>(out: &mut W, fields: &[String])
+    -> Result<(), Error>
+{
+    let fields = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>();
+    try!(writeln!(out, "{}", fields.join(",")));
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String
+{
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// What the report shows of each bot in the order of the ranking.
+struct Row
+{
+    ix: usize,
+    games: usize,
+    wins: usize,
+    quarantined: bool,
+}
+
+fn rows(state: &Checkpoint, config: &Config) -> Vec<Row>
+{
+    tournament::ranking(state, config)
+        .into_iter()
+        .map(|ix| {
+            let mut games = 0;
+            let mut wins = 0;
+            for game in state.games.iter() {
+                if let Some(seat) = game.players.iter().position(|&p| p == ix) {
+                    games += 1;
+                    if game.rankings[seat] == 1 {
+                        wins += 1;
+                    }
+                }
+            }
+            Row {
+                ix: ix,
+                games: games,
+                wins: wins,
+                quarantined: config.is_quarantined(&state.bots[ix]),
+            }
+        })
+        .collect()
+}
+
+fn write_leaderboard_csv(path: &Path,
+                         state: &Checkpoint,
+                         config: &Config,
+                         rows: &[Row])
+    -> Result<(), Error>
+{
+    let mut out = try!(File::create(path));
+    let mut header = vec!["rank".to_owned(),
+                          "bot".to_owned(),
+                          "games".to_owned(),
+                          "wins".to_owned(),
+                          "failures".to_owned(),
+                          "quarantined".to_owned()];
+    for system in config.rating_systems.iter() {
+        header.push(format!("{} mu", system.name()));
+        header.push(format!("{} sigma", system.name()));
+    }
+    header.push("name".to_owned());
+    header.push("spec".to_owned());
+    try!(write_csv_row(&mut out, &header));
+    for (pos, row) in rows.iter().enumerate() {
+        let entry = &state.bots[row.ix];
+        let mut fields = vec![(pos + 1).to_string(),
+                              (row.ix + 1).to_string(),
+                              row.games.to_string(),
+                              row.wins.to_string(),
+                              entry.failures.to_string(),
+                              row.quarantined.to_string()];
+        for system in config.rating_systems.iter() {
+            let skill = entry.skill(&**system);
+            fields.push(skill.mu.to_string());
+            fields.push(skill.sigma.to_string());
+        }
+        fields.push(entry.name.clone().unwrap_or_default());
+        fields.push(entry.bot.to_string());
+        try!(write_csv_row(&mut out, &fields));
+    }
+    Ok(())
+}
+
+fn write_head_to_head_csv(path: &Path,
+                          wins: &[Vec<usize>],
+                          rows: &[Row])
+    -> Result<(), Error>
+{
+    let mut out = try!(File::create(path));
+    let mut header = vec![String::new()];
+    header.extend(rows.iter().map(|r| format!("bot {}", r.ix + 1)));
+    try!(write_csv_row(&mut out, &header));
+    for a in rows.iter() {
+        let mut fields = vec![format!("bot {}", a.ix + 1)];
+        fields.extend(rows.iter().map(|b| wins[a.ix][b.ix].to_string()));
+        try!(write_csv_row(&mut out, &fields));
+    }
+    Ok(())
+}
+
+fn write_ratings_csv(path: &Path, history: &[Vec<Skill>])
+    -> Result<(), Error>
+{
+    let mut out = try!(File::create(path));
+    try!(write_csv_row(&mut out,
+                       &["game".to_owned(),
+                         "bot".to_owned(),
+                         "mu".to_owned(),
+                         "sigma".to_owned()]));
+    for (game_ix, skills) in history.iter().enumerate() {
+        for (ix, skill) in skills.iter().enumerate() {
+            try!(write_csv_row(&mut out,
+                               &[(game_ix + 1).to_string(),
+                                 (ix + 1).to_string(),
+                                 skill.mu.to_string(),
+                                 skill.sigma.to_string()]));
+        }
+    }
+    Ok(())
+}
+
+fn write_games_csv(path: &Path, state: &Checkpoint) -> Result<(), Error>
+{
+    let mut out = try!(File::create(path));
+    try!(write_csv_row(&mut out,
+                       &["game".to_owned(),
+                         "width".to_owned(),
+                         "height".to_owned(),
+                         "seed".to_owned(),
+                         "bots".to_owned(),
+                         "ranks".to_owned(),
+                         "failed".to_owned(),
+                         "replay".to_owned()]));
+    let join = |ixs: &[usize], offset: usize| {
+        ixs.iter()
+           .map(|ix| (ix + offset).to_string())
+           .collect::<Vec<_>>()
+           .join(" ")
+    };
+    for (game_ix, game) in state.games.iter().enumerate() {
+        try!(write_csv_row(&mut out,
+                           &[(game_ix + 1).to_string(),
+                             game.width.to_string(),
+                             game.height.to_string(),
+                             game.seed.to_string(),
+                             join(&game.players, 1),
+                             join(&game.rankings, 0),
+                             join(&game.failed, 1),
+                             game.hlt_path.display().to_string()]));
+    }
+    Ok(())
+}
+
+/// An SVG plot of the `mu` of the best bots over the games.
+fn rating_plot(history: &[Vec<Skill>], rows: &[Row]) -> String
+{
+    let plotted = rows.iter().take(N_PLOTTED).collect::<Vec<_>>();
+    if history.is_empty() || plotted.is_empty() {
+        return String::new();
+    }
+    let mus = history.iter()
+                     .flat_map(|skills| {
+                         plotted.iter().map(move |r| skills[r.ix].mu)
+                     })
+                     .collect::<Vec<_>>();
+    let lo = mus.iter().cloned().fold(mus[0], f64::min);
+    let hi = mus.iter().cloned().fold(mus[0], f64::max);
+    let span = if hi > lo { hi - lo } else { 1.0 };
+    let x = |game_ix: usize| {
+        PLOT_WIDTH * (game_ix + 1) as f64 / history.len() as f64
+    };
+    let y = |mu: f64| PLOT_HEIGHT * (1.0 - (mu - lo) / span);
+    let mut svg = format!("<svg width=\"{}\" height=\"{}\">\n",
+                          PLOT_WIDTH,
+                          PLOT_HEIGHT);
+    for (row, color) in plotted.iter().zip(PLOT_COLORS.iter()) {
+        let points = history.iter()
+                            .enumerate()
+                            .map(|(game_ix, skills)| {
+                                format!("{:.1},{:.1}",
+                                        x(game_ix),
+                                        y(skills[row.ix].mu))
+                            })
+                            .collect::<Vec<_>>();
+        svg.push_str(&format!("<polyline fill=\"none\" stroke=\"{}\" \
+                               points=\"{}\"><title>bot {}</title>\
+                               </polyline>\n",
+                              color,
+                              points.join(" "),
+                              row.ix + 1));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_html(path: &Path,
+              state: &Checkpoint,
+              config: &Config,
+              rows: &[Row],
+              wins: &[Vec<usize>],
+              history: &[Vec<Skill>])
+    -> Result<(), Error>
+{
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut out = try!(File::create(path));
+    try!(write!(out,
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>Tournament report</title>\n<style>\n\
+                 table {{ border-collapse: collapse; }}\n\
+                 td, th {{ border: 1px solid #ccc; padding: 2px 6px; \
+                 text-align: right; }}\n\
+                 </style>\n</head>\n<body>\n\
+                 <h1>Tournament report</h1>\n\
+                 <p>Master seed {}, generation {}, {} games</p>\n",
+                state.seed,
+                state.generation + 1,
+                state.games.len()));
+
+    try!(write!(out, "<h2>Leaderboard</h2>\n<table>\n<tr><th>#</th>\
+                      <th>Bot</th><th>Games</th><th>Wins</th>\
+                      <th>Failures</th>"));
+    for system in config.rating_systems.iter() {
+        try!(write!(out, "<th>{}</th>", escape_html(system.name())));
+    }
+    try!(write!(out, "<th>Spec</th></tr>\n"));
+    for (pos, row) in rows.iter().enumerate() {
+        let entry = &state.bots[row.ix];
+        try!(write!(out,
+                    "<tr><td>{}</td><td>{}{}{}</td><td>{}</td><td>{}</td>\
+                     <td>{}</td>",
+                    pos + 1,
+                    row.ix + 1,
+                    entry.name
+                         .as_ref()
+                         .map(|n| format!(" {}", escape_html(n)))
+                         .unwrap_or_default(),
+                    if row.quarantined { " (quarantined)" } else { "" },
+                    row.games,
+                    row.wins,
+                    entry.failures));
+        for system in config.rating_systems.iter() {
+            let skill = entry.skill(&**system);
+            try!(write!(out,
+                        "<td>{:.*} ± {:.*}</td>",
+                        1,
+                        skill.mu,
+                        1,
+                        skill.sigma));
+        }
+        try!(write!(out,
+                    "<td style=\"text-align: left\">{}</td></tr>\n",
+                    escape_html(&entry.bot.to_string())));
+    }
+    try!(write!(out, "</table>\n"));
+
+    try!(write!(out,
+                "<h2>Ratings by {}</h2>\n{}",
+                escape_html(config.rating_systems[0].name()),
+                rating_plot(history, rows)));
+
+    try!(write!(out, "<h2>Head to head</h2>\n<p>Games in which the bot \
+                      of the row ranked above the bot of the column</p>\n\
+                      <table>\n<tr><th></th>"));
+    for row in rows.iter() {
+        try!(write!(out, "<th>{}</th>", row.ix + 1));
+    }
+    try!(write!(out, "</tr>\n"));
+    for a in rows.iter() {
+        try!(write!(out, "<tr><th>{}</th>", a.ix + 1));
+        for b in rows.iter() {
+            let won = wins[a.ix][b.ix];
+            let lost = wins[b.ix][a.ix];
+            if won + lost == 0 {
+                try!(write!(out, "<td></td>"));
+            } else {
+                let share = won as f64 / (won + lost) as f64;
+                try!(write!(out,
+                            "<td style=\"background: hsl({:.0}, 60%, 80%)\" \
+                             title=\"{}-{}\">{}</td>",
+                            120.0 * share,
+                            won,
+                            lost,
+                            won));
+            }
+        }
+        try!(write!(out, "</tr>\n"));
+    }
+    try!(write!(out, "</table>\n"));
+
+    try!(write!(out, "<h2>Games</h2>\n<table>\n<tr><th>#</th><th>Map</th>\
+                      <th>Seed</th><th>Bots by rank</th><th>Replay</th>\
+                      </tr>\n"));
+    for (game_ix, game) in state.games.iter().enumerate() {
+        let mut seats = game.players
+                            .iter()
+                            .zip(game.rankings.iter())
+                            .collect::<Vec<_>>();
+        seats.sort_by_key(|&(_, &rank)| rank);
+        let bots = seats.iter()
+                        .map(|&(&ix, _)| {
+                            if game.failed.contains(&ix) {
+                                format!("{}†", ix + 1)
+                            } else {
+                                (ix + 1).to_string()
+                            }
+                        })
+                        .collect::<Vec<_>>();
+        let link = route_between(dir, &game.hlt_path)
+                       .unwrap_or_else(|_| game.hlt_path.clone());
+        try!(write!(out,
+                    "<tr><td>{}</td><td>{}x{}</td><td>{}</td>\
+                     <td style=\"text-align: left\">{}</td>\
+                     <td><a href=\"{}\">{}</a></td></tr>\n",
+                    game_ix + 1,
+                    game.width,
+                    game.height,
+                    game.seed,
+                    bots.join(" "),
+                    escape_html(&link.display().to_string()),
+                    escape_html(&game.hlt_path
+                                     .file_name()
+                                     .map(|n| n.to_string_lossy().into_owned())
+                                     .unwrap_or_default())));
+    }
+    try!(write!(out, "</table>\n</body>\n</html>\n"));
+    Ok(())
+}
+
+/// Writes the report of the tournament `state` into `dir` creating the
+/// directory if needed.  Returns the path of the HTML file.
+pub fn write(state: &Checkpoint, config: &Config, dir: &Path)
+    -> Result<PathBuf, Error>
+{
+    try!(fs::create_dir_all(dir));
+    let rows = rows(state, config);
+    let wins = head_to_head(state);
+    let history = rating_history(state, config);
+    try!(write_leaderboard_csv(&dir.join("leaderboard.csv"),
+                               state,
+                               config,
+                               &rows));
+    try!(write_head_to_head_csv(&dir.join("head_to_head.csv"), &wins, &rows));
+    try!(write_ratings_csv(&dir.join("ratings.csv"), &history));
+    try!(write_games_csv(&dir.join("games.csv"), state));
+    let html_path = dir.join("report.html");
+    try!(write_html(&html_path, state, config, &rows, &wins, &history));
+    Ok(html_path)
+}
+
+#[cfg(test)]
+mod test {
+
+    use checkpoint::fixture::{game, population};
+    use tournament::Config;
+    use super::{csv_field, head_to_head, rating_history};
+
+    #[test]
+    fn test_csv_field()
+    {
+        assert_eq!(csv_field("bot 1"), "bot 1");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_head_to_head_and_history()
+    {
+        let mut state = population(&[25.0, 25.0, 25.0]);
+        state.games.push(game(vec![0, 1, 2]));
+        state.games.push(game(vec![2, 1]));
+        let mut failed = game(vec![0, 2]);
+        failed.failed = vec![2];
+        state.games.push(failed);
+        let wins = head_to_head(&state);
+        assert_eq!(wins[0], vec![0, 1, 1]);
+        assert_eq!(wins[1], vec![0, 0, 1]);
+        assert_eq!(wins[2], vec![0, 1, 0]);
+        let history = rating_history(&state, &Config::default());
+        assert_eq!(history.len(), 3);
+        assert!(history[0][0].mu > history[0][2].mu);
+        // The game with a failed player is not rated
+        assert_eq!(history[2], history[1]);
+    }
+
+    #[test]
+    fn test_history_starts_from_initial_ratings()
+    {
+        // The first bot is a veteran and the second one a newcomer
+        let mut state = population(&[40.0, 25.0]);
+        state.bots[0].initial_ratings = state.bots[0].ratings.clone();
+        state.games.push(game(vec![1, 0]));
+        let history = rating_history(&state, &Config::default());
+        // One loss does not bring a veteran down to the level of a newcomer
+        assert!(history[0][0].mu > 30.0);
+        assert!(history[0][0].sigma < 3.0);
+    }
+}
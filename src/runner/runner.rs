@@ -15,24 +15,31 @@
 // You should have received a copy of the GNU General Public License along
 // with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::BTreeMap;
+use std::env;
 use std::error;
 use std::fmt;
 use std::fs::{self, File};
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::string::String;
+use std::sync::Arc;
 
+use ua::brain;
+use ua::params::{Params, Schema};
+use ua::replay::{PlayerStats, Replay};
 use ua::space::Space;
 
+use inproc::InProcess;
+
 #[derive(Debug)]
 pub enum Error
 {
     Io(io::Error),
     Environment(String),
     Runtime(String),
+    Format(String),
 }
 
 impl error::Error for Error
@@ -43,6 +50,7 @@ impl error::Error for Error
             Error::Io(ref e) => e.description(),
             Error::Environment(_) => "something wrong with environment",
             Error::Runtime(_) => "execution failed during run-time",
+            Error::Format(_) => "malformed file",
         }
     }
 }
@@ -67,6 +75,9 @@ impl fmt::Display for Error
             Error::Runtime(ref stderr) => {
                 write!(f, "run-time failure: \"{}\"", stderr)
             }
+            Error::Format(ref details) => {
+                write!(f, "malformed file: {}", details)
+            }
         }
     }
 }
@@ -75,25 +86,49 @@ impl fmt::Display for Error
 pub struct Env
 {
     work_dir: PathBuf,
-    halite_exe: PathBuf,
+    /// The halite executable as given; it is looked up only when needed
+    halite_exe: Option<PathBuf>,
 }
 
 impl Env
 {
-    pub fn new<P>(work_dir: P, halite_exe: P) -> Result<Self, Error>
+    pub fn new<P>(work_dir: P) -> Result<Self, Error>
+        where P: AsRef<Path>
+    {
+        Ok(Env {
+            work_dir: try!(work_dir.as_ref().canonicalize()),
+            halite_exe: None,
+        })
+    }
+
+    pub fn with_halite<P>(mut self, halite_exe: P) -> Self
+        where P: AsRef<Path>
+    {
+        self.halite_exe = Some(halite_exe.as_ref().to_path_buf());
+        self
+    }
+
+    /// The same environment working in `work_dir`.
+    pub fn in_dir<P>(&self, work_dir: P) -> Result<Self, Error>
         where P: AsRef<Path>
     {
-        let work_dir = try!(work_dir.as_ref().canonicalize());
-        let halite_exe = try!(halite_exe.as_ref().canonicalize());
         Ok(Env {
-            work_dir: work_dir,
-            halite_exe: halite_exe,
+            work_dir: try!(work_dir.as_ref().canonicalize()),
+            halite_exe: self.halite_exe.clone(),
         })
     }
 
-    pub fn path_to_halite(&self) -> &Path
+    /// The absolute path to the halite executable.
+    pub fn path_to_halite(&self) -> Result<PathBuf, Error>
     {
-        self.halite_exe.as_path()
+        let path = try!(self.halite_exe.as_ref().ok_or_else(|| {
+            Error::Environment("no halite executable given".to_owned())
+        }));
+        path.canonicalize().map_err(|e| {
+            Error::Environment(format!("halite executable {}: {}",
+                                       path.display(),
+                                       e))
+        })
     }
 
     pub fn work_dir(&self) -> &Path
@@ -113,7 +148,7 @@ impl Env
 pub struct BotArgs
 {
     brain: Option<String>,
-    params: BTreeMap<String, f32>,
+    params: Params,
 }
 
 impl BotArgs
@@ -122,7 +157,7 @@ impl BotArgs
     {
         BotArgs {
             brain: None,
-            params: BTreeMap::new(),
+            params: Params::new(),
         }
     }
 
@@ -171,11 +206,81 @@ impl Bot
         })
     }
 
+    /// A bot played by `brain` within the runner.  The runner itself
+    /// stands in for the executable so the bot changes with the runner.
+    pub fn hosted(brain: &str) -> Result<Self, Error>
+    {
+        let mut bot = try!(Bot::new(try!(env::current_exe())));
+        bot.brain(brain);
+        Ok(bot)
+    }
+
+    /// Whether the bot is played by a brain within the runner.
+    pub fn is_hosted(&self) -> bool
+    {
+        env::current_exe()
+            .and_then(|exe| exe.canonicalize())
+            .map(|exe| exe == self.exe_path)
+            .unwrap_or(false)
+    }
+
+    pub fn exe_path(&self) -> &Path
+    {
+        self.exe_path.as_path()
+    }
+
+    pub fn brain_name(&self) -> Option<&str>
+    {
+        self.args.brain.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn params(&self) -> &Params
+    {
+        &self.args.params
+    }
+
+    pub fn brain(&mut self, name: &str) -> &mut Self
+    {
+        self.args.brain = Some(name.to_owned());
+        self
+    }
+
     pub fn set_param(&mut self, key: &str, value: f32) -> &mut Self
     {
         self.args.params.insert(key.to_owned(), value);
         self
     }
+
+    pub fn set_params(&mut self, params: &Params) -> &mut Self
+    {
+        for (k, v) in params.iter() {
+            self.set_param(k, *v);
+        }
+        self
+    }
+
+    /// Asks the bot for the parameters its brains understand.  A hosted
+    /// bot is not asked but its brain is looked up in the registry.
+    pub fn schema(&self) -> Result<Schema, Error>
+    {
+        if self.is_hosted() {
+            let name = self.brain_name().unwrap_or(brain::default_name());
+            return brain::mold_of(name)
+                       .map(|mold| mold.schema())
+                       .map_err(Error::Environment);
+        }
+        let mut command = Command::new(&self.exe_path);
+        if let Some(ref brain) = self.args.brain {
+            command.arg("-b").arg(brain);
+        }
+        let output = try!(command.arg("--schema").output());
+        if !output.status.success() {
+            return Err(Error::Runtime(String::from_utf8_lossy(&output.stderr)
+                                          .into_owned()));
+        }
+        Schema::parse(&String::from_utf8_lossy(&output.stdout))
+            .map_err(|e| Error::Runtime(e.to_string()))
+    }
 }
 
 impl fmt::Display for Bot
@@ -190,10 +295,9 @@ impl fmt::Display for Bot
     }
 }
 
-fn write_script(env: &Env, bot: &Bot) -> Result<Vec<u8>, Error>
+fn write_script(exe_path: &Path, bot: &Bot) -> Result<Vec<u8>, Error>
 {
     let mut buffer = Vec::new();
-    let exe_path = try!(env.relative_from(&bot.exe_path));
     try!(write!(buffer, "#!/bin/bash\nexec {}", exe_path.display()));
     if !bot.args.is_empty() {
         try!(write!(buffer, " {}", bot.args));
@@ -202,12 +306,41 @@ fn write_script(env: &Env, bot: &Bot) -> Result<Vec<u8>, Error>
     Ok(buffer)
 }
 
-pub fn mk_bot_script(env: &Env, bot: &Bot) -> Result<PathBuf, Error>
+fn hex_digest(buffer: &[u8]) -> String
 {
     use md5;
     use data_encoding::hex;
-    let buffer = try!(write_script(env, bot));
-    let digest = hex::encode(&md5::compute(&buffer));
+    hex::encode(&md5::compute(buffer))
+}
+
+/// The identity of `bot` independent of the working directory: the digest
+/// of its script with the absolute path to the executable.  The time the
+/// executable was modified is part of the digest so that a rebuilt bot is
+/// not mistaken for the old one.
+pub fn bot_digest(bot: &Bot) -> Result<String, Error>
+{
+    let mut buffer = try!(write_script(&bot.exe_path, bot));
+    let modified = try!(try!(fs::metadata(&bot.exe_path)).modified());
+    try!(write!(buffer, "# {:?}\n", modified));
+    Ok(hex_digest(&buffer))
+}
+
+fn script_for(env: &Env, bot: &Bot) -> Result<Vec<u8>, Error>
+{
+    let exe_path = try!(env.relative_from(&bot.exe_path));
+    write_script(&exe_path, bot)
+}
+
+/// The name of the script `mk_bot_script` writes for `bot` in `env`.
+pub fn script_name(env: &Env, bot: &Bot) -> Result<String, Error>
+{
+    Ok(hex_digest(&try!(script_for(env, bot))))
+}
+
+pub fn mk_bot_script(env: &Env, bot: &Bot) -> Result<PathBuf, Error>
+{
+    let buffer = try!(script_for(env, bot));
+    let digest = hex_digest(&buffer);
     let mut path = env.work_dir().to_path_buf();
     path.push(digest);
     if !path.is_file() {
@@ -223,6 +356,13 @@ pub fn mk_bot_script(env: &Env, bot: &Bot) -> Result<PathBuf, Error>
     Ok(path)
 }
 
+fn read_log(path: &Path) -> io::Result<String>
+{
+    let mut log = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut log)));
+    Ok(log)
+}
+
 #[derive(Clone, Debug)]
 pub struct Match<'a>
 {
@@ -238,6 +378,40 @@ pub struct Outcome
     pub seed: u64,
     pub rankings: Vec<usize>,
     pub hlt_path: PathBuf,
+    /// The statistics of each player read from the replay
+    pub stats: Vec<PlayerStats>,
+    /// The players that crashed or timed out
+    pub failures: Vec<Failure>,
+}
+
+/// A player the environment dropped from the game for crashing or timing
+/// out; the environment does not tell the two apart.
+#[derive(Clone, Debug)]
+pub struct Failure
+{
+    /// The index of the player in the match
+    pub player: usize,
+    /// The turn the player was dropped on according to the replay
+    pub turn: Option<usize>,
+    /// The path to the log the environment wrote about the player
+    pub log_path: Option<PathBuf>,
+    /// The contents of the log
+    pub log: Option<String>,
+}
+
+impl fmt::Display for Failure
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        try!(write!(f, "player {} failed", self.player + 1));
+        if let Some(turn) = self.turn {
+            try!(write!(f, " on turn {}", turn));
+        }
+        if let Some(ref log) = self.log {
+            try!(write!(f, ":\n{}", log.trim_right()));
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Match<'a>
@@ -264,6 +438,27 @@ impl<'a> Match<'a>
         self
     }
 
+    pub fn width(&self) -> i16
+    {
+        self.width
+    }
+
+    pub fn height(&self) -> i16
+    {
+        self.height
+    }
+
+    /// The seed of the map if one was given.
+    pub fn map_seed(&self) -> Option<usize>
+    {
+        self.seed
+    }
+
+    pub fn bots(&self) -> &[&'a Bot]
+    {
+        &self.bots
+    }
+
     fn parse_output(&self, output: &[u8], env: &Env) -> Result<Outcome, Error>
     {
         use std::borrow::Cow;
@@ -314,30 +509,110 @@ impl<'a> Match<'a>
                 return Err(err(&output));
             }
         }
-        // The two odd extra lines
-        for _ in 0..2 {
-            let () = try!(lines.next().ok_or(err(&output)).and_then(|l| {
-                if l == " " {
-                    Ok(())
-                } else {
-                    Err(err(&output))
+        // The players that timed out or crashed and the logs written about
+        // them in the same order
+        let mut failures = Vec::new();
+        {
+            let l = try!(lines.next().ok_or(err(&output)));
+            for tag in l.split_whitespace() {
+                match tag.parse::<usize>() {
+                    Ok(tag) if 1 <= tag && tag <= n_bots => {
+                        failures.push(Failure {
+                            player: tag - 1,
+                            turn: None,
+                            log_path: None,
+                            log: None,
+                        });
+                    }
+                    _ => return Err(err(&output)),
                 }
-            }));
+            }
+        }
+        {
+            let l = try!(lines.next().ok_or(err(&output)));
+            for (failure, name) in failures.iter_mut()
+                                           .zip(l.split_whitespace()) {
+                failure.log_path = Some(env.work_dir().join(name));
+            }
         }
         if lines.next().is_none() {
             Ok(Outcome {
                 seed: seed,
                 rankings: rankings,
                 hlt_path: hlt_path,
+                stats: Vec::new(),
+                failures: failures,
             })
         } else {
             Err(err(&output))
         }
     }
 
-    pub fn run(&mut self, env: &Env) -> Result<Outcome, Error>
+    /// Plays the match with the halite environment.
+    pub fn run(&self, env: &Env) -> Result<Outcome, Error>
+    {
+        self.run_with(&External, env)
+    }
+
+    pub fn run_with(&self, engine: &Engine, env: &Env)
+        -> Result<Outcome, Error>
     {
-        let mut command = Command::new(env.path_to_halite());
+        engine.play(self, env)
+    }
+}
+
+/// A way of playing matches.
+pub trait Engine: Send + Sync + fmt::Debug
+{
+    fn name(&self) -> &str;
+
+    /// Whether the engine plays the brains within the runner rather than
+    /// bot executables.
+    fn is_in_process(&self) -> bool
+    {
+        false
+    }
+
+    /// Tells whether the engine can play `bot` at all.
+    fn check(&self, _bot: &Bot) -> Result<(), Error>
+    {
+        Ok(())
+    }
+
+    /// Plays `game` leaving the replay and the logs in the working
+    /// directory of `env`.
+    fn play(&self, game: &Match, env: &Env) -> Result<Outcome, Error>;
+}
+
+/// Plays the matches by running the halite environment executable with
+/// the bot executables.
+#[derive(Clone, Debug)]
+pub struct External;
+
+impl Engine for External
+{
+    fn name(&self) -> &str
+    {
+        "external"
+    }
+
+    fn check(&self, bot: &Bot) -> Result<(), Error>
+    {
+        if bot.is_hosted() {
+            Err(Error::Environment(format!("bot {} has no executable of its \
+                                            own; play it in-process",
+                                           bot)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn play(&self, game: &Match, env: &Env) -> Result<Outcome, Error>
+    {
+        for bot in game.bots.iter() {
+            try!(self.check(bot));
+        }
+        let mut command = Command::new(try!(env.path_to_halite()));
         let work_dir = env.work_dir();
         if !work_dir.is_dir() {
             return Err(Error::Environment(format!("The working directory \
@@ -348,17 +623,30 @@ impl<'a> Match<'a>
         command.current_dir(work_dir)
                .arg("-q")
                .arg("-d")
-               .arg(format!("{} {}", self.width, self.height));
-        if let Some(seed) = self.seed {
+               .arg(format!("{} {}", game.width, game.height));
+        if let Some(seed) = game.seed {
             command.arg("-s").arg(format!("{}", seed));
         }
-        for bot in self.bots.iter() {
+        for bot in game.bots.iter() {
             let bot_path = try!(mk_bot_script(env, bot));
             command.arg(bot_path);
         }
         let output = try!(command.output());
         if output.status.success() {
-            self.parse_output(&output.stdout, env)
+            let mut outcome = try!(game.parse_output(&output.stdout, env));
+            let replay = try!(Replay::load(&outcome.hlt_path).map_err(|e| {
+                Error::Format(format!("replay {}: {:?}",
+                                      outcome.hlt_path.display(),
+                                      e))
+            }));
+            outcome.stats = replay.player_stats();
+            for failure in outcome.failures.iter_mut() {
+                failure.turn = outcome.stats[failure.player].eliminated;
+                failure.log = failure.log_path
+                                     .as_ref()
+                                     .and_then(|p| read_log(p).ok());
+            }
+            Ok(outcome)
         } else {
             Err(Error::Runtime(String::from_utf8_lossy(&output.stderr)
                 .into_owned()))
@@ -366,7 +654,49 @@ impl<'a> Match<'a>
     }
 }
 
-mod util {
+/// Parses the name of an engine.
+pub fn parse_engine(name: &str) -> Result<Arc<Engine>, String>
+{
+    match name {
+        "external" => Ok(Arc::new(External)),
+        "in-process" => Ok(Arc::new(InProcess::new())),
+        _ => Err(format!("no engine with name '{}'", name)),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+
+    use ua::space::Space;
+
+    use super::{Bot, Env, Match};
+
+    #[test]
+    fn test_parse_output_with_failure()
+    {
+        let env = Env::new(env::temp_dir()).unwrap();
+        let bot = Bot::new("/bin/sh").unwrap();
+        let game = Match::new(&Space::with_dims(30, 30)).bot(&bot).bot(&bot);
+        let output = "a\nb\n123.hlt 42\n1 1\n2 2\n2 \n2-42.log \n";
+        let outcome = game.parse_output(output.as_bytes(), &env).unwrap();
+        assert_eq!(outcome.seed, 42);
+        assert_eq!(outcome.rankings, vec![1, 2]);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].player, 1);
+        assert_eq!(outcome.failures[0].log_path,
+                   Some(env.work_dir().join("2-42.log")));
+        let output = "a\nb\n123.hlt 42\n1 1\n2 2\n \n \n";
+        let outcome = game.parse_output(output.as_bytes(), &env).unwrap();
+        assert!(outcome.failures.is_empty());
+        assert!(game.parse_output(b"a\nb\n123.hlt 42\n1 1\n", &env)
+                    .is_err());
+    }
+}
+
+pub mod util {
 
     use std::io;
     use std::path::{Path, PathBuf};
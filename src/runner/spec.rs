@@ -0,0 +1,180 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Bot spec files listing the bots of a mixed population.
+//!
+//! Each section names a bot and gives its executable, relative to the spec
+//! file, and optionally its brain and parameters:
+//!
+//! ```text
+//! [lone]
+//! exe = ../target/release/MyBot
+//! brain = lone_expander
+//! aggression_weight = 42
+//!
+//! [teddy]
+//! exe = ../target/release/MyBot
+//! brain = teddy
+//! count = 5
+//!
+//! [v12]
+//! exe = ../bots/MyBot-v12
+//! ```
+//!
+//! A section without `exe` is a bot hosted by the runner (see
+//! `Bot::hosted`) for the in-process engine; it must name its brain.
+//!
+//! With `count` the population gets that many bots of the section.  As
+//! identical copies would only tie with each other the parameters not given
+//! in the section are sampled from the bot's schema for each copy.  A bot
+//! of a single copy is passed no parameters beyond those given so any
+//! executable that speaks the Halite protocol will do.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use ua::params::Params;
+
+use runner::Error;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BotSpec
+{
+    pub name: String,
+    /// The executable or `None` for a hosted bot
+    pub exe: Option<PathBuf>,
+    pub brain: Option<String>,
+    pub params: Params,
+    /// The number of bots of this spec in the population
+    pub count: usize,
+}
+
+fn syntax_error(line: usize, message: &str) -> String
+{
+    format!("line {}: {}", line, message)
+}
+
+/// Parses the spec file `text` with the executables relative to `base`.
+pub fn parse(text: &str, base: &Path) -> Result<Vec<BotSpec>, String>
+{
+    let mut specs: Vec<BotSpec> = Vec::new();
+    for (ix, line) in text.lines().enumerate() {
+        let err = |message: &str| syntax_error(ix + 1, message);
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') || line.len() < 3 {
+                return Err(err("bad section header"));
+            }
+            let name = line[1..line.len() - 1].trim().to_owned();
+            if specs.iter().any(|s| s.name == name) {
+                return Err(err(&format!("bot '{}' given twice", name)));
+            }
+            specs.push(BotSpec {
+                name: name,
+                exe: None,
+                brain: None,
+                params: Params::new(),
+                count: 1,
+            });
+            continue;
+        }
+        let mut kv = line.splitn(2, '=');
+        let key = kv.next().unwrap_or("").trim();
+        let value = try!(kv.next().ok_or_else(|| err("expected KEY = VALUE")))
+                        .trim();
+        if key.is_empty() {
+            return Err(err("missing key"));
+        }
+        let spec = try!(specs.last_mut()
+                             .ok_or_else(|| err("expected a [bot] section")));
+        match key {
+            "exe" => spec.exe = Some(base.join(value)),
+            "brain" => spec.brain = Some(value.to_owned()),
+            "count" => {
+                let count = try!(value.parse::<usize>()
+                                      .map_err(|_| err("bad count")));
+                if count == 0 {
+                    return Err(err("bad count"));
+                }
+                spec.count = count;
+            }
+            _ => {
+                let value = try!(value.parse::<f32>()
+                                      .map_err(|_| err("bad value")));
+                spec.params.insert(key.to_owned(), value);
+            }
+        }
+    }
+    if let Some(spec) = specs.iter()
+                             .find(|s| s.exe.is_none() && s.brain.is_none()) {
+        return Err(format!("bot '{}' has neither exe nor brain", spec.name));
+    }
+    Ok(specs)
+}
+
+pub fn load<P>(path: P) -> Result<Vec<BotSpec>, Error>
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let mut text = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text)));
+    parse(&text, path.parent().unwrap_or(Path::new(".")))
+        .map_err(|e| Error::Format(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::path::{Path, PathBuf};
+
+    use super::parse;
+
+    #[test]
+    fn test_parse()
+    {
+        let text = "# Sparring partners\n\
+                    [lone]\n\
+                    exe = MyBot\n\
+                    brain = lone_expander\n\
+                    weight = 42\n\
+                    \n\
+                    [v12]\n\
+                    exe = /bots/MyBot-v12  # the last release\n\
+                    count = 3\n";
+        let specs = parse(text, Path::new("/work")).unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].name, "lone");
+        assert_eq!(specs[0].exe, Some(PathBuf::from("/work/MyBot")));
+        assert_eq!(specs[0].brain, Some("lone_expander".to_owned()));
+        assert_eq!(specs[0].params["weight"], 42.0);
+        assert_eq!(specs[0].count, 1);
+        assert_eq!(specs[1].exe, Some(PathBuf::from("/bots/MyBot-v12")));
+        assert_eq!(specs[1].brain, None);
+        assert_eq!(specs[1].count, 3);
+        assert!(parse("exe = MyBot\n", Path::new(".")).is_err());
+        let hosted = parse("[a]\nbrain = teddy\n", Path::new(".")).unwrap();
+        assert_eq!(hosted[0].exe, None);
+        assert!(parse("[a]\ncount = 2\n", Path::new(".")).is_err());
+        assert!(parse("[a]\nexe = x\n[a]\nexe = y\n", Path::new("."))
+                    .is_err());
+        assert!(parse("[a]\nexe = x\ncount = 0\n", Path::new(".")).is_err());
+    }
+}
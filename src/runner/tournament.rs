@@ -0,0 +1,474 @@
+// Copyright (C) 2016 Matti Hänninen
+//
+// This file is part of Umpteenth Anion.
+//
+// Umpteenth Anion is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// Umpteenth Anion is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License
+// for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with Umpteenth Anion.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use rand::{self, Rng, SeedableRng, StdRng};
+
+use ua::params::Schema;
+use ua::util::f32_cmp;
+
+use cache::Cache;
+use checkpoint::{Checkpoint, Entry, Game, Settings};
+use matchmaking::{self, Policy};
+use pool::{Job, Pool};
+use rating::{Bbt, RatingSystem, System};
+use report;
+use runner::{Bot, Engine, Env, Error, External};
+use spec::BotSpec;
+
+const CHECKPOINT_FILE: &'static str = "tournament.json";
+
+/// The streams of random numbers derived from the master seed.
+pub const POPULATE_STREAM: usize = 0;
+pub const ROUND_STREAM: usize = 1;
+pub const BREED_STREAM: usize = 2;
+
+#[derive(Clone, Debug)]
+pub struct Config
+{
+    pub population_size: usize,
+    pub n_games: usize,
+    /// The smallest and the largest number of players in a game
+    pub n_players: (usize, usize),
+    /// The side lengths of the square maps the games are played on
+    pub map_sizes: Vec<i16>,
+    /// The number of games played at the same time.  A new tournament plays
+    /// its games in rounds of this many and keeps to that when resumed.
+    pub n_workers: usize,
+    /// The file the state of the tournament is saved to after every round;
+    /// by default `tournament.json` in the working directory
+    pub checkpoint: Option<PathBuf>,
+    /// The master seed all the randomness of the tournament derives from;
+    /// picked at random when not given
+    pub seed: Option<usize>,
+    /// The number of crashes and timeouts after which a bot is left out of
+    /// the games
+    pub max_failures: usize,
+    /// The rating systems the bots are rated by; the first one is used to
+    /// pick the players of the games and to rank the bots
+    pub rating_systems: Vec<System>,
+    /// The policy by which the players of the games are picked
+    pub matchmaking: Policy,
+    /// The directory the report of the tournament is written to, if any
+    pub report: Option<PathBuf>,
+    /// Whether to reuse the outcomes of the games played before
+    pub cache: bool,
+    /// The engine the games are played with
+    pub engine: Arc<Engine>,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Config {
+            population_size: 100,
+            n_games: 1000,
+            n_players: (2, 6),
+            map_sizes: vec![25, 30, 35, 40],
+            n_workers: 1,
+            checkpoint: None,
+            seed: None,
+            max_failures: 2,
+            rating_systems: vec![Rc::new(Bbt { beta: 25.0 / 6.0 })],
+            matchmaking: Rc::new(matchmaking::Sigma),
+            report: None,
+            cache: true,
+            engine: Arc::new(External),
+        }
+    }
+}
+
+impl Config
+{
+    pub fn checkpoint_path(&self, env: &Env) -> PathBuf
+    {
+        match self.checkpoint {
+            Some(ref path) => path.clone(),
+            None => env.work_dir().join(CHECKPOINT_FILE),
+        }
+    }
+
+    /// A pool of workers playing the games of the tournament.
+    pub fn pool(&self, env: &Env) -> Result<Pool, Error>
+    {
+        let pool = try!(Pool::new(env, self.n_workers));
+        if self.cache {
+            Ok(pool.with_cache(try!(Cache::in_work_dir(env))))
+        } else {
+            Ok(pool)
+        }
+    }
+
+    pub fn primary_rating(&self) -> &RatingSystem
+    {
+        &*self.rating_systems[0]
+    }
+
+    /// The options making up the tournament as saved in the checkpoint.
+    pub fn settings(&self) -> Settings
+    {
+        Settings {
+            n_players: self.n_players,
+            map_sizes: self.map_sizes.clone(),
+            max_failures: self.max_failures,
+            rating_systems: self.rating_systems
+                                .iter()
+                                .map(|system| system.spec())
+                                .collect(),
+            matchmaking: self.matchmaking.name().to_owned(),
+            engine: self.engine.name().to_owned(),
+        }
+    }
+
+    /// Whether `entry` has failed too often to be played any more.
+    pub fn is_quarantined(&self, entry: &Entry) -> bool
+    {
+        entry.failures >= self.max_failures
+    }
+}
+
+/// Updates the ratings of the players of `game` that did not fail.  Those
+/// that did fail are not rated as their loss says nothing about how well
+/// their parameters play.
+pub fn update_ratings(bots: &mut [Entry], game: &Game, systems: &[System])
+{
+    let (players, rankings): (Vec<usize>, Vec<usize>) =
+        game.players
+            .iter()
+            .zip(game.rankings.iter())
+            .filter(|&(ix, _)| !game.failed.contains(ix))
+            .map(|(&ix, &rank)| (ix, rank))
+            .unzip();
+    if players.len() < 2 {
+        return;
+    }
+    for system in systems.iter() {
+        let old_skills = players.iter()
+                                .map(|&ix| bots[ix].skill(&**system))
+                                .collect::<Vec<_>>();
+        let new_skills = system.update(&old_skills, &rankings);
+        for (&ix, skill) in players.iter().zip(new_skills.into_iter()) {
+            bots[ix].ratings.insert(system.name().to_owned(), skill);
+        }
+    }
+}
+
+/// A random number generator for the `stream` of the tournament `state`
+/// at the current point of the tournament.  Seeding each step afresh from
+/// the master seed keeps a tournament resumed from a checkpoint on the same
+/// track as an uninterrupted one.
+pub fn derive_rng(state: &Checkpoint, stream: usize) -> StdRng
+{
+    let key = [state.seed, stream, state.generation, state.games.len()];
+    SeedableRng::from_seed(&key[..])
+}
+
+fn master_seed(config: &Config) -> usize
+{
+    match config.seed {
+        Some(seed) => seed,
+        None => rand::thread_rng().gen::<u32>() as usize,
+    }
+}
+
+fn randomize_bot<R: Rng>(bot: &mut Bot, schema: &Schema, rng: &mut R)
+{
+    bot.set_params(&schema.sample(rng));
+}
+
+/// Creates a population of unrated bots with parameters sampled from
+/// `schema`.
+pub fn populate(proto: &Bot, schema: &Schema, config: &Config) -> Checkpoint
+{
+    let mut state = Checkpoint::new(vec![Entry::new(proto.clone());
+                                         config.population_size],
+                                    master_seed(config));
+    state.round_size = config.n_workers;
+    state.settings = config.settings();
+    let mut rng = derive_rng(&state, POPULATE_STREAM);
+    for entry in state.bots.iter_mut() {
+        randomize_bot(&mut entry.bot, schema, &mut rng);
+    }
+    state
+}
+
+/// Creates a population of the bots in `specs`.  The parameters not given
+/// for a spec of several copies are sampled from the schema of its bot.
+pub fn populate_from_specs(specs: &[BotSpec], config: &Config)
+    -> Result<Checkpoint, Error>
+{
+    let mut state = Checkpoint::new(Vec::new(), master_seed(config));
+    state.round_size = config.n_workers;
+    state.settings = config.settings();
+    let mut rng = derive_rng(&state, POPULATE_STREAM);
+    for spec in specs.iter() {
+        let mut bot = match spec.exe {
+            Some(ref exe) => try!(Bot::new(exe)),
+            None => try!(Bot::hosted(spec.brain.as_ref().unwrap())),
+        };
+        if let Some(ref brain) = spec.brain {
+            bot.brain(brain);
+        }
+        let schema = if spec.count > 1 {
+            Some(try!(bot.schema()))
+        } else {
+            None
+        };
+        for _ in 0..spec.count {
+            let mut copy = bot.clone();
+            if let Some(ref schema) = schema {
+                randomize_bot(&mut copy, schema, &mut rng);
+            }
+            copy.set_params(&spec.params);
+            let mut entry = Entry::new(copy);
+            entry.name = Some(spec.name.clone());
+            state.bots.push(entry);
+        }
+    }
+    Ok(state)
+}
+
+/// Plays games in the tournament `state` until it has `n_games` of them.
+/// The games are played in rounds of the size recorded in the state, which
+/// is saved after every round.  Given the master seed and `n_games` the
+/// games are the same from run to run however often the tournament is
+/// interrupted and whatever the number of workers of the `pool`.
+pub fn play(pool: &Pool,
+            state: &mut Checkpoint,
+            n_games: usize,
+            config: &Config,
+            checkpoint_path: &Path)
+    -> Result<(), Error>
+{
+    // Run matches in rounds of the recorded size.  The games of a round have
+    // no bots in common and their outcomes are applied in the order the
+    // games were drawn, so the ratings do not depend on which game happens
+    // to finish first.  An interrupted round is played again from the
+    // start since the state is only saved between rounds.
+    while state.games.len() < n_games {
+        let candidates = (0..state.bots.len())
+                             .filter(|&ix| {
+                                 !config.is_quarantined(&state.bots[ix])
+                             })
+                             .collect::<Vec<_>>();
+        if candidates.len() < config.n_players.0 {
+            return Err(Error::Runtime("too many bots quarantined to fill \
+                                       a game"
+                                          .to_owned()));
+        }
+        let sigmas = candidates.iter()
+                               .map(|&ix| {
+                                   state.bots[ix]
+                                       .skill(config.primary_rating())
+                                       .sigma
+                               })
+                               .collect::<Vec<_>>();
+        let max_sigma = sigmas.iter().cloned().fold(0.0, f64::max);
+        let min_sigma = sigmas.iter().cloned().fold(max_sigma, f64::min);
+        let n_played = state.games.len();
+        let mut rng = derive_rng(state, ROUND_STREAM);
+        let n_round = state.round_size.min(n_games - n_played);
+        let groups = config.matchmaking
+                           .pick(state, config, &candidates, n_round, &mut rng);
+        if groups.is_empty() {
+            return Err(Error::Runtime(format!("matchmaking policy '{}' \
+                                               found no game to play",
+                                              config.matchmaking.name())));
+        }
+        let mut jobs = Vec::new();
+        for group in groups.iter() {
+            let map_size = *rng.choose(&config.map_sizes).unwrap();
+            println!("Game {}, Map = {}x{}, Players = {}, Sigma = ({:.*}, \
+                      {:.*})",
+                     n_played + jobs.len() + 1,
+                     map_size,
+                     map_size,
+                     group.len(),
+                     2,
+                     min_sigma,
+                     2,
+                     max_sigma);
+            jobs.push(Job {
+                width: map_size,
+                height: map_size,
+                seed: Some(rng.gen::<u32>() as usize),
+                bots: group.iter()
+                           .map(|&ix| state.bots[ix].bot.clone())
+                           .collect(),
+                engine: config.engine.clone(),
+            });
+        }
+        let outcomes = pool.run_all(&jobs);
+        for ((job, players), outcome) in jobs.into_iter()
+                                             .zip(groups.into_iter())
+                                             .zip(outcomes.into_iter()) {
+            let outcome = try!(outcome);
+            let failed = outcome.failures
+                                .iter()
+                                .map(|f| players[f.player])
+                                .collect::<Vec<_>>();
+            for (failure, &ix) in outcome.failures.iter().zip(failed.iter()) {
+                let entry = &mut state.bots[ix];
+                entry.failures += 1;
+                println!("Bot {}: {}", ix + 1, failure);
+                if config.is_quarantined(entry) {
+                    println!("Bot {} quarantined: {}", ix + 1, entry.bot);
+                }
+            }
+            let game = Game {
+                width: job.width,
+                height: job.height,
+                seed: outcome.seed,
+                players: players,
+                rankings: outcome.rankings,
+                failed: failed,
+                hlt_path: outcome.hlt_path,
+            };
+            update_ratings(&mut state.bots, &game, &config.rating_systems);
+            state.games.push(game);
+        }
+        try!(state.save(checkpoint_path));
+    }
+    Ok(())
+}
+
+/// The indices of the bots in `state` from the highest `mu` to the lowest
+/// by `system` with the quarantined bots last.
+pub fn ranking_by(state: &Checkpoint,
+                  config: &Config,
+                  system: &RatingSystem)
+    -> Vec<usize>
+{
+    let mus = state.bots
+                   .iter()
+                   .map(|e| e.skill(system).mu)
+                   .collect::<Vec<_>>();
+    let mut order = (0..state.bots.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        let quarantined = |ix: usize| config.is_quarantined(&state.bots[ix]);
+        match quarantined(a).cmp(&quarantined(b)) {
+            Ordering::Equal => f32_cmp(&(mus[b] as f32), &(mus[a] as f32)),
+            ordering => ordering,
+        }
+    });
+    order
+}
+
+/// The ranking of the bots by the primary rating system.
+pub fn ranking(state: &Checkpoint, config: &Config) -> Vec<usize>
+{
+    ranking_by(state, config, config.primary_rating())
+}
+
+/// Prints the leaderboard of every rating system side by side so that one
+/// can see how much the systems agree.
+pub fn print_leaderboards(state: &Checkpoint, config: &Config)
+{
+    let systems = &config.rating_systems;
+    let rankings = systems.iter()
+                          .map(|s| ranking_by(state, config, &**s))
+                          .collect::<Vec<_>>();
+    print!("{:>4}", "#");
+    for system in systems.iter() {
+        print!("  {:<24}", system.name());
+    }
+    println!("");
+    for pos in 0..state.bots.len() {
+        print!("{:>4}", pos + 1);
+        for (system, ranking) in systems.iter().zip(rankings.iter()) {
+            let ix = ranking[pos];
+            let skill = state.bots[ix].skill(&**system);
+            print!("  {:<24}",
+                   format!("bot {:<4} {:.*} ± {:.*}",
+                           ix + 1,
+                           1,
+                           skill.mu,
+                           1,
+                           skill.sigma));
+        }
+        println!("");
+    }
+}
+
+/// Plays the games remaining in the tournament `state` and prints the bots
+/// best first.  The state is saved after every round of games, so an
+/// interruption loses at most the round being played, and the report is
+/// written at the end if asked for.
+pub fn run(env: &Env, state: &mut Checkpoint, config: &Config)
+    -> Result<(), Error>
+{
+    let pool = try!(config.pool(env));
+    println!("Master seed {}", state.seed);
+    try!(play(&pool,
+              state,
+              config.n_games,
+              config,
+              &config.checkpoint_path(env)));
+    print_leaderboards(state, config);
+    for ix in ranking(state, config) {
+        let entry = &state.bots[ix];
+        match entry.name {
+            Some(ref name) => {
+                println!("bot {} ({}): {}", ix + 1, name, entry.bot)
+            }
+            None => println!("bot {}: {}", ix + 1, entry.bot),
+        }
+    }
+    write_report(state, config)
+}
+
+/// Writes the report of the tournament into `config.report` if given.
+pub fn write_report(state: &Checkpoint, config: &Config) -> Result<(), Error>
+{
+    if let Some(ref dir) = config.report {
+        let path = try!(report::write(state, config, dir));
+        println!("Report written to {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use ua::params::Schema;
+
+    use checkpoint::fixture;
+    use super::{Config, populate};
+
+    #[test]
+    fn test_populate_is_reproducible()
+    {
+        let schema = Schema::new().param("weight", 50.0, 0.0, 100.0, "");
+        let proto = fixture::bot();
+        let mut config = Config::default();
+        config.population_size = 5;
+        config.seed = Some(7);
+        let a = populate(&proto, &schema, &config);
+        let b = populate(&proto, &schema, &config);
+        assert_eq!(a.seed, 7);
+        for (a, b) in a.bots.iter().zip(b.bots.iter()) {
+            assert_eq!(a.bot.params(), b.bot.params());
+        }
+        config.seed = Some(8);
+        let c = populate(&proto, &schema, &config);
+        assert!(a.bots[0].bot.params() != c.bots[0].bot.params());
+    }
+}